@@ -20,4 +20,68 @@ pub enum ErrorCode {
     InvalidStaker,
     #[msg("No Tokens Staked")]
     NoTokenStaked,
+    #[msg("APR must be greater than zero")]
+    InvalidApr,
+    #[msg("End date must be in the future")]
+    InvalidEndDate,
+    #[msg("Invalid pool name")]
+    InvalidPoolName,
+    #[msg("Invalid default multiplier")]
+    InvalidMultiplier,
+    #[msg("Fee config is outside the allowed limits")]
+    InvalidFeeConfig,
+    #[msg("Invalid Fee Recipient")]
+    InvalidFeeRecipient,
+    #[msg("Pool has already been migrated")]
+    PoolAlreadyMigrated,
+    #[msg("Invalid pool account")]
+    InvalidPoolAccount,
+    #[msg("Stake mint does not match the pool token")]
+    InvalidStakeMint,
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Amount exceeds the staked balance")]
+    InsufficientStakedAmount,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Stake entry still holds tokens or rewards")]
+    StakeEntryNotEmpty,
+    #[msg("Pool is still accruing rewards")]
+    PoolStillAccruing,
+    #[msg("No excess rewards to withdraw")]
+    NoExcessRewards,
+    #[msg("Pool still has stakers")]
+    PoolHasStakers,
+    #[msg("Pool does not hold enough rewards for this stake")]
+    InsufficientRewardFunding,
+    #[msg("Reward vault cannot cover the claim")]
+    InsufficientRewards,
+    #[msg("Pool still owes unpaid rewards")]
+    UnpaidRewardsOutstanding,
+    #[msg("Emergency withdraw is not enabled for this pool")]
+    EmergencyWithdrawDisabled,
+    #[msg("Invalid lock tier")]
+    InvalidLockTier,
+    #[msg("Top-ups must use the lock tier of the existing position")]
+    LockTierMismatch,
+    #[msg("Invalid boost curve")]
+    InvalidBoostCurve,
+    #[msg("Invalid reward mode")]
+    InvalidRewardMode,
+    #[msg("Invalid or missing reward stream account")]
+    InvalidRewardStream,
+    #[msg("Pool already has the maximum number of reward streams")]
+    TooManyRewardStreams,
+    #[msg("Pool rewards are not paid in the stake mint")]
+    CompoundNotSupported,
+    #[msg("No rewards to compound")]
+    NoRewardsToCompound,
+    #[msg("Invalid stake entry account")]
+    InvalidStakeEntry,
+    #[msg("Stake entry has already been migrated")]
+    StakeEntryAlreadyMigrated,
+    #[msg("Pool still has reward streams")]
+    RewardStreamsOutstanding,
+    #[msg("Stake entry has no expired boost")]
+    BoostNotExpired,
 }
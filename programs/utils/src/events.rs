@@ -0,0 +1,238 @@
+use anchor_lang::prelude::*;
+
+use crate::{BoostCurve, FeeConfig, LockPolicy, LockTier};
+
+#[event]
+pub struct GlobalConfigUpdated {
+    pub super_admin: Pubkey,
+    pub pending_super_admin: Option<Pubkey>,
+    pub fee_recipient: Pubkey,
+    pub platform_fee: u64,
+}
+
+#[event]
+pub struct PoolInitialized {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub token_address: Pubkey,
+    pub reward_mint: Pubkey,
+    pub identifier: String,
+    pub apr: u64,
+    pub end_date: Option<i64>,
+    pub reward_amount: u64,
+}
+
+#[event]
+pub struct PoolUpdated {
+    pub pool: Pubkey,
+    pub apr: u64,
+    pub end_date: Option<i64>,
+    pub pool_name: String,
+    pub default_multiplier: u64,
+    pub min_stake_seconds: u32,
+    pub lock_policy: LockPolicy,
+    pub fee_config: FeeConfig,
+    pub lock_tiers: Vec<LockTier>,
+    pub boost_curve: BoostCurve,
+    pub compound_tip_bps: u16,
+}
+
+#[event]
+pub struct PoolFunded {
+    pub pool: Pubkey,
+    pub depositor: Pubkey,
+    pub amount: u64,
+    pub total_rewards_funded: u64,
+}
+
+#[event]
+pub struct ExcessRewardsWithdrawn {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PoolClosed {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    /// Reward tokens left in the vault and returned to the authority.
+    pub withdrawn: u64,
+}
+
+#[event]
+pub struct FeeLimitsUpdated {
+    pub pool: Pubkey,
+}
+
+#[event]
+pub struct PoolMigrated {
+    pub pool: Pubkey,
+}
+
+#[event]
+pub struct StakeEntryCreated {
+    pub pool: Pubkey,
+    pub stake_entry: Pubkey,
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct StakeEntryMigrated {
+    pub pool: Pubkey,
+    pub stake_entry: Pubkey,
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct StakeEntryClosed {
+    pub pool: Pubkey,
+    pub stake_entry: Pubkey,
+    pub owner: Pubkey,
+    /// Tokens left in the entry account and returned to the owner.
+    pub swept: u64,
+}
+
+#[event]
+pub struct AutoCompoundUpdated {
+    pub stake_entry: Pubkey,
+    pub owner: Pubkey,
+    pub enabled: bool,
+}
+
+#[event]
+pub struct DelegateUpdated {
+    pub stake_entry: Pubkey,
+    pub owner: Pubkey,
+    pub delegate: Option<Pubkey>,
+}
+
+#[event]
+pub struct Staked {
+    pub pool: Pubkey,
+    pub stake_entry: Pubkey,
+    pub staker: Pubkey,
+    pub amount: u64,
+    pub entry_amount: u64,
+    pub total_staked: u64,
+}
+
+#[event]
+pub struct Compounded {
+    pub pool: Pubkey,
+    pub stake_entry: Pubkey,
+    pub staker: Pubkey,
+    pub amount: u64,
+    pub entry_amount: u64,
+}
+
+#[event]
+pub struct CompoundCranked {
+    pub pool: Pubkey,
+    pub cranker: Pubkey,
+    /// Number of entries that were compounded.
+    pub entries: u32,
+    pub tip: u64,
+}
+
+#[event]
+pub struct BoostExpired {
+    pub pool: Pubkey,
+    pub stake_entry: Pubkey,
+    pub effective_stake: u64,
+}
+
+#[event]
+pub struct Unstaked {
+    pub pool: Pubkey,
+    pub stake_entry: Pubkey,
+    pub staker: Pubkey,
+    pub amount: u64,
+    /// Early exit penalty, including the super admin's share.
+    pub penalty: u64,
+    /// Part of the amount sent to the super admin.
+    pub admin_fee: u64,
+    /// Regular unstake fee sent to the pool owner.
+    pub unstake_fee: u64,
+    pub reward: u64,
+    /// Principal that reached the staker after fees, net of any token
+    /// transfer fee.
+    pub received: u64,
+}
+
+#[event]
+pub struct EmergencyWithdrawUpdated {
+    pub pool: Pubkey,
+    pub enabled: bool,
+    pub fee_bps: u16,
+}
+
+#[event]
+pub struct EmergencyWithdrawn {
+    pub pool: Pubkey,
+    pub stake_entry: Pubkey,
+    pub staker: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+    /// Net of any token transfer fee.
+    pub received: u64,
+}
+
+#[event]
+pub struct RewardsClaimed {
+    pub pool: Pubkey,
+    pub stake_entry: Pubkey,
+    pub staker: Pubkey,
+    pub amount: u64,
+    /// Rewards still owed to the staker after this claim.
+    pub reward_iou: u64,
+}
+
+#[event]
+pub struct RewardStreamAdded {
+    pub pool: Pubkey,
+    pub reward_stream: Pubkey,
+    pub reward_mint: Pubkey,
+    pub authority: Pubkey,
+    pub rate_per_second: u64,
+    pub end_time: i64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct StreamExcessWithdrawn {
+    pub pool: Pubkey,
+    pub reward_stream: Pubkey,
+    pub authority: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct RewardStreamClosed {
+    pub pool: Pubkey,
+    pub reward_stream: Pubkey,
+    pub authority: Pubkey,
+    /// Tokens left in the stream vault and returned to the authority.
+    pub withdrawn: u64,
+}
+
+#[event]
+pub struct StreamRewardsClaimed {
+    pub pool: Pubkey,
+    pub reward_stream: Pubkey,
+    pub stake_entry: Pubkey,
+    pub staker: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PoolFrozen {
+    pub pool: Pubkey,
+    pub by: Pubkey,
+}
+
+#[event]
+pub struct PoolUnfrozen {
+    pub pool: Pubkey,
+    pub by: Pubkey,
+}
@@ -1,30 +1,181 @@
 use anchor_lang::prelude::*;
 pub mod utils;
+use crate::math::{apply_bps, checked_add, checked_sub, mul_div, mul_div_u64, to_u64, Rounding};
+use crate::utils::{harvest_withheld_fees, resize_account};
 use anchor_lang::system_program;
-use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
-use anchor_spl::token_interface::{transfer_checked, TransferChecked};
-use crate::utils::resize_account;
-use solana_program::{pubkey, pubkey::Pubkey};
+use anchor_lang::Discriminator;
+use anchor_spl::associated_token::{get_associated_token_address_with_program_id, AssociatedToken};
+use anchor_spl::token_interface::{
+    close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
+    TransferChecked,
+};
+use solana_program::pubkey::Pubkey;
 mod errors;
+pub mod events;
+pub mod math;
 
 // This is your program's public key and it will update
 // automatically when you build the project.
 declare_id!("13cmrtqpPfd4nMor9P6HeboFM7JpQPX3nLPBSQeeVTSj");
 
 pub const STAKE_POOL_PREFIX: &str = "stake-pool";
-pub const STAKE_POOL_DEFAULT_SIZE: usize = 8 + 1 + 32 + 16 + 16 + 32 + 16 + 16 + 32 + 1 + 24 + 24;
+pub const STAKE_POOL_DEFAULT_SIZE: usize = 8
+    + 1
+    + 32
+    + 16
+    + 16
+    + 32
+    + 16
+    + 16
+    + 32
+    + 1
+    + 24
+    + 24
+    + 4
+    + 16
+    + 8
+    + 1
+    + 8
+    + 8
+    + 32
+    + 32
+    + 16
+    + 8
+    + 8
+    + 8
+    + 8
+    + 1
+    + 2
+    + 4
+    + MAX_LOCK_TIERS * 6
+    + 1
+    + 4
+    + MAX_BOOST_STEPS * 12
+    + 34
+    + 1
+    + 2;
 pub const STAKE_ENTRY_PREFIX: &str = "stake-entry";
-pub const SUPER_ADMIN: Pubkey = pubkey!("Bx6Z6XxCSdwtqmiKP9prwU7m8NDuUcA11FtPdSZ5Fw9B");
-pub const PLATFORM_FEE: u64 = 500000000; // 0.5 SOL
+pub const GLOBAL_CONFIG_PREFIX: &str = "global-config";
+pub const REWARD_STREAM_PREFIX: &str = "reward-stream";
+pub const REWARD_STREAM_SIZE: usize =
+    8 + 1 + 32 + 1 + 32 + 32 + 8 + 8 + 16 + 8 + 8 + 8 + 32 + 8 + 8;
+pub const GLOBAL_CONFIG_SIZE: usize = 8 + 1 + 32 + 33 + 32 + 8 + 8;
+pub const MAX_POOL_NAME_LENGTH: usize = 64;
+pub const STAKE_ENTRY_SIZE: usize = 8
+    + 1
+    + 32
+    + 8
+    + 32
+    + 32
+    + 8
+    + 5
+    + 8
+    + 16
+    + 8
+    + 33
+    + 8
+    + 1
+    + 8
+    + 16 * MAX_REWARD_STREAMS
+    + 8 * MAX_REWARD_STREAMS
+    + 1;
+pub const BASIS_POINTS: u128 = 10_000;
+pub const SECONDS_PER_YEAR: u128 = 365 * 24 * 60 * 60;
+pub const REWARD_PRECISION: u128 = 1_000_000_000_000;
+/// `default_multiplier` value that leaves rewards unchanged (1x).
+pub const MULTIPLIER_BASE: u64 = 10_000;
+pub const MAX_DEFAULT_MULTIPLIER: u64 = 10 * MULTIPLIER_BASE;
+pub const MAX_EMERGENCY_WITHDRAW_FEE_BPS: u16 = 1_000;
+pub const MAX_LOCK_TIERS: usize = 8;
+pub const MAX_BOOST_STEPS: usize = 8;
+pub const MAX_REWARD_STREAMS: usize = 4;
+pub const MAX_COMPOUND_TIP_BPS: u16 = 500;
+pub const MAX_BOOST_MULTIPLIER: u64 = 5 * MULTIPLIER_BASE;
 
 #[program]
 mod dyme_staking {
     use super::*;
+    pub fn init_global_config(
+        ctx: Context<InitGlobalConfigCtx>,
+        ix: InitGlobalConfigIx,
+    ) -> Result<()> {
+        let global_config = &mut ctx.accounts.global_config;
+        global_config.bump = ctx.bumps.global_config;
+        global_config.super_admin = ix.super_admin;
+        global_config.pending_super_admin = None;
+        global_config.fee_recipient = ix.fee_recipient;
+        global_config.platform_fee = ix.platform_fee;
+        global_config.default_fee_limits = FeeLimits::DEFAULT;
+
+        global_config.emit_updated();
+        Ok(())
+    }
+
+    pub fn set_super_admin(ctx: Context<SetSuperAdminCtx>, new_super_admin: Pubkey) -> Result<()> {
+        let global_config = &mut ctx.accounts.global_config;
+        global_config.pending_super_admin = Some(new_super_admin);
+
+        global_config.emit_updated();
+        Ok(())
+    }
+
+    pub fn accept_super_admin(ctx: Context<AcceptSuperAdminCtx>) -> Result<()> {
+        let global_config = &mut ctx.accounts.global_config;
+        global_config.super_admin = ctx.accounts.payer.key();
+        global_config.pending_super_admin = None;
+
+        global_config.emit_updated();
+        Ok(())
+    }
+
+    pub fn set_platform_fee(ctx: Context<SetPlatformFeeCtx>, ix: SetPlatformFeeIx) -> Result<()> {
+        let global_config = &mut ctx.accounts.global_config;
+
+        if let Some(platform_fee) = ix.platform_fee {
+            global_config.platform_fee = platform_fee;
+        }
+
+        if let Some(fee_recipient) = ix.fee_recipient {
+            global_config.fee_recipient = fee_recipient;
+        }
+
+        if let Some(default_fee_limits) = ix.default_fee_limits {
+            default_fee_limits.validate()?;
+            global_config.default_fee_limits = default_fee_limits;
+        }
+
+        global_config.emit_updated();
+        Ok(())
+    }
+
     pub fn init_pool(ctx: Context<InitPoolCtx>, ix: InitPoolIx) -> Result<()> {
         let bump = ctx.bumps.stake_pool;
         let identifier = ix.identifier;
-        let new_stake_pool = StakePool {
+        let now = Clock::get()?.unix_timestamp;
+
+        // Same rules as `update_pool`, so a pool never starts out in a state
+        // it could not be updated into.
+        if ix.apr == 0 && ix.reward_mode.uses_apr() {
+            return err!(errors::ErrorCode::InvalidApr);
+        }
+
+        if ix.end_date.is_some_and(|end_date| end_date <= now) {
+            return err!(errors::ErrorCode::InvalidEndDate);
+        }
+
+        if ix.pool_name.is_empty() || ix.pool_name.len() > MAX_POOL_NAME_LENGTH {
+            return err!(errors::ErrorCode::InvalidPoolName);
+        }
+
+        if ix.default_multiplier == 0 || ix.default_multiplier > MAX_DEFAULT_MULTIPLIER {
+            return err!(errors::ErrorCode::InvalidMultiplier);
+        }
+
+        LockTier::validate_tiers(&ix.lock_tiers)?;
+        ix.boost_curve.validate()?;
+        ix.reward_mode.validate()?;
+
+        let mut new_stake_pool = StakePool {
             bump,
             authority: ix.authority,
             total_staked: 0,
@@ -34,40 +185,65 @@ mod dyme_staking {
             end_date: ix.end_date,
             pool_name: ix.pool_name,
             default_multiplier: ix.default_multiplier,
-            created_at: Clock::get().unwrap().unix_timestamp,
+            created_at: now,
             is_active: true,
             identifier,
+            min_stake_seconds: 0,
+            reward_per_token_stored: 0,
+            last_reward_update: now,
+            lock_policy: ix.lock_policy,
+            fee_config: ix.fee_config.unwrap_or(FeeConfig::DEFAULT),
+            fee_limits: ctx.accounts.global_config.default_fee_limits,
+            reward_mint: ctx.accounts.reward_mint.key(),
+            reward_vault: ctx.accounts.reward_vault.key(),
+            total_reward_weight: 0,
+            total_rewards_owed: 0,
+            total_rewards_funded: 0,
+            total_rewards_paid: 0,
+            total_reward_ious: 0,
+            emergency_withdraw_enabled: false,
+            emergency_withdraw_fee_bps: 0,
+            lock_tiers: ix.lock_tiers,
+            boost_curve: ix.boost_curve,
+            reward_mode: ix.reward_mode,
+            reward_stream_count: 0,
+            compound_tip_bps: 0,
         };
 
-        msg!("account, {:?}", ctx.accounts.mint);
+        new_stake_pool
+            .fee_config
+            .validate(&new_stake_pool.fee_limits)?;
 
-        if Some(ctx.accounts.mint.mint_authority).is_some()
-            && (ctx.accounts.mint.mint_authority
-                != solana_program::program_option::COption::Some(ctx.accounts.payer.key()))
+        if ctx.accounts.mint.mint_authority
+            != solana_program::program_option::COption::Some(ctx.accounts.payer.key())
+            && ctx.accounts.payer.key() != ctx.accounts.global_config.super_admin
         {
-            if ctx.accounts.payer.key() != SUPER_ADMIN {
-                return err!(errors::ErrorCode::InvalidTokenAuthority);
-            }
+            return err!(errors::ErrorCode::InvalidTokenAuthority);
         }
 
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.payer_token_account.to_account_info(),
-            to: ctx.accounts.pool_token_account.to_account_info(),
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.payer_reward_token_account.to_account_info(),
+            to: ctx.accounts.reward_vault.to_account_info(),
             authority: ctx.accounts.payer.to_account_info(),
+            mint: ctx.accounts.reward_mint.to_account_info(),
         };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_program = ctx.accounts.reward_token_program.to_account_info();
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
 
-        token::transfer(cpi_ctx, ix.amount)?;
+        let vault_before = ctx.accounts.reward_vault.amount;
+        transfer_checked(cpi_ctx, ix.amount, ctx.accounts.reward_mint.decimals)?;
+        ctx.accounts.reward_vault.reload()?;
+        new_stake_pool.total_rewards_funded =
+            checked_sub(ctx.accounts.reward_vault.amount, vault_before)?;
 
         let cpi_context = CpiContext::new(
             ctx.accounts.system_program.to_account_info(),
             system_program::Transfer {
                 from: ctx.accounts.payer.to_account_info(),
-                to: ctx.accounts.super_admin.to_account_info(),
+                to: ctx.accounts.fee_recipient.to_account_info(),
             },
         );
-        system_program::transfer(cpi_context, PLATFORM_FEE)?;
+        system_program::transfer(cpi_context, ctx.accounts.global_config.platform_fee)?;
 
         let stake_pool = &mut ctx.accounts.stake_pool;
         let new_space = new_stake_pool.try_to_vec()?.len() + 8;
@@ -79,6 +255,17 @@ mod dyme_staking {
             &ctx.accounts.system_program.to_account_info(),
         )?;
 
+        emit!(events::PoolInitialized {
+            pool: stake_pool.key(),
+            authority: new_stake_pool.authority,
+            token_address: new_stake_pool.token_address,
+            reward_mint: new_stake_pool.reward_mint,
+            identifier: new_stake_pool.identifier.clone(),
+            apr: new_stake_pool.apr,
+            end_date: new_stake_pool.end_date,
+            reward_amount: new_stake_pool.total_rewards_funded,
+        });
+
         stake_pool.set_inner(new_stake_pool);
 
         Ok(())
@@ -89,45 +276,210 @@ mod dyme_staking {
         stake_entry.bump = ctx.bumps.stake_entry;
         stake_entry.pool = ctx.accounts.stake_pool.key();
         stake_entry.stake_mint = ctx.accounts.stake_mint.key();
+        stake_entry.owner = ctx.accounts.payer.key();
+        stake_entry.delegate = None;
         stake_entry.amount = 0;
+        stake_entry.reward_per_token_paid = 0;
+        stake_entry.pending_rewards = 0;
+
+        emit!(events::StakeEntryCreated {
+            pool: stake_entry.pool,
+            stake_entry: stake_entry.key(),
+            owner: stake_entry.owner,
+        });
+        Ok(())
+    }
+
+    pub fn set_auto_compound(ctx: Context<SetAutoCompoundCtx>, enabled: bool) -> Result<()> {
+        let stake_entry = &mut ctx.accounts.stake_entry;
+        stake_entry.auto_compound = enabled;
+
+        emit!(events::AutoCompoundUpdated {
+            stake_entry: stake_entry.key(),
+            owner: stake_entry.owner,
+            enabled,
+        });
+        Ok(())
+    }
+
+    pub fn set_delegate(ctx: Context<SetDelegateCtx>, delegate: Option<Pubkey>) -> Result<()> {
+        let stake_entry = &mut ctx.accounts.stake_entry;
+        stake_entry.delegate = delegate;
+
+        emit!(events::DelegateUpdated {
+            stake_entry: stake_entry.key(),
+            owner: stake_entry.owner,
+            delegate,
+        });
+        Ok(())
+    }
+
+    pub fn close_stake_entry(ctx: Context<CloseStakeEntryCtx>) -> Result<()> {
+        let stake_entry = &ctx.accounts.stake_entry;
+
+        if stake_entry.amount != 0
+            || stake_entry.pending_rewards != 0
+            || stake_entry.reward_iou != 0
+            || stake_entry.stream_pending_rewards.iter().any(|&r| r != 0)
+        {
+            return err!(errors::ErrorCode::StakeEntryNotEmpty);
+        }
+
+        let pool = stake_entry.pool;
+        let seeds = &[
+            STAKE_ENTRY_PREFIX.as_bytes(),
+            pool.as_ref(),
+            stake_entry.stake_mint.as_ref(),
+            stake_entry.owner.as_ref(),
+            &[stake_entry.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        // Anyone can send tokens to the entry account, so whatever it still
+        // holds goes back to the owner rather than blocking the close.
+        let swept = ctx.accounts.entry_token_account.amount;
+        if swept > 0 {
+            let sweep_accounts = TransferChecked {
+                from: ctx.accounts.entry_token_account.to_account_info(),
+                to: ctx.accounts.payer_token_account.to_account_info(),
+                authority: stake_entry.to_account_info(),
+                mint: ctx.accounts.stake_mint.to_account_info(),
+            };
+            let sweep_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                sweep_accounts,
+                signer_seeds,
+            );
+            transfer_checked(sweep_ctx, swept, ctx.accounts.stake_mint.decimals)?;
+        }
+
+        harvest_withheld_fees(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.stake_mint.to_account_info(),
+            &ctx.accounts.entry_token_account.to_account_info(),
+        )?;
+
+        let accounts = CloseAccount {
+            account: ctx.accounts.entry_token_account.to_account_info(),
+            destination: ctx.accounts.payer.to_account_info(),
+            authority: stake_entry.to_account_info(),
+        };
+        let ctx_close = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            accounts,
+            signer_seeds,
+        );
+        close_account(ctx_close)?;
+
+        emit!(events::StakeEntryClosed {
+            pool,
+            stake_entry: stake_entry.key(),
+            owner: stake_entry.owner,
+            swept,
+        });
         Ok(())
     }
 
     pub fn stake_token(ctx: Context<InitStakeCtx>, ix: InitStakeIx) -> Result<()> {
         let stake_entry = &mut ctx.accounts.stake_entry;
         let stake_pool = &mut ctx.accounts.stake_pool;
+        let now = Clock::get()?.unix_timestamp;
 
         if !stake_pool.is_active {
             return err!(errors::ErrorCode::PoolFrozen);
         }
 
-        if stake_pool.end_date.is_some()
-            && Clock::get().unwrap().unix_timestamp > stake_pool.end_date.unwrap()
-        {
+        if stake_pool.end_date.is_some() && now > stake_pool.end_date.unwrap() {
             return err!(errors::ErrorCode::StakePoolHasEnded);
         }
 
-        if stake_entry.amount > 0 {
-            return err!(errors::ErrorCode::UnstakeAllTokens);
+        if ix.amount == 0 {
+            return err!(errors::ErrorCode::InvalidAmount);
+        }
+
+        let is_new_position = stake_entry.amount == 0;
+
+        let tier = *stake_pool
+            .lock_tiers
+            .get(ix.tier as usize)
+            .ok_or(errors::ErrorCode::InvalidLockTier)?;
+        if !is_new_position && ix.tier != stake_entry.tier {
+            return err!(errors::ErrorCode::LockTierMismatch);
         }
 
-        let cpi_accounts = Transfer {
+        let balance_before = ctx.accounts.entry_token_account.amount;
+
+        let cpi_accounts = TransferChecked {
             from: ctx.accounts.payer_token_account.to_account_info(),
             to: ctx.accounts.entry_token_account.to_account_info(),
             authority: ctx.accounts.payer.to_account_info(),
+            mint: ctx.accounts.stake_mint.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
 
-        token::transfer(cpi_ctx, ix.amount)?;
+        transfer_checked(cpi_ctx, ix.amount, ctx.accounts.stake_mint.decimals)?;
 
-        stake_entry.apr = ix.apr;
-        stake_entry.last_staker = ctx.accounts.payer.key();
-        stake_entry.last_staked_at = Clock::get().unwrap().unix_timestamp;
-        stake_entry.amount = stake_entry.amount.checked_add(ix.amount).unwrap();
-        stake_entry.min_stake_seconds = ix.min_stake_seconds;
-        stake_pool.total_staked = stake_pool.total_staked + ix.amount;
-        stake_pool.total_stakers = stake_pool.total_stakers.checked_add(1).expect("Add error");
+        // Mints with a transfer fee deliver less than requested, so only
+        // credit what actually reached the entry account.
+        ctx.accounts.entry_token_account.reload()?;
+        let received = checked_sub(ctx.accounts.entry_token_account.amount, balance_before)?;
+
+        // Otherwise an empty entry would be counted as a new staker on
+        // every call.
+        if received == 0 {
+            return err!(errors::ErrorCode::InvalidAmount);
+        }
+
+        stake_pool.update_rewards(now)?;
+        stake_entry.settle_rewards(stake_pool)?;
+        stake_entry.settle_reward_streams(stake_pool, ctx.remaining_accounts, now)?;
+
+        let min_stake_seconds = match tier.min_stake_seconds.max(stake_pool.min_stake_seconds) {
+            0 => None,
+            seconds => Some(seconds),
+        };
+
+        if is_new_position {
+            stake_entry.tier = ix.tier;
+            stake_entry.last_staked_at = now;
+            stake_entry.min_stake_seconds = min_stake_seconds;
+            stake_pool.total_stakers = stake_pool
+                .total_stakers
+                .checked_add(1)
+                .ok_or(errors::ErrorCode::MathOverflow)?;
+        } else {
+            stake_entry.last_staked_at = match stake_pool.lock_policy {
+                LockPolicy::Reset => now,
+                LockPolicy::WeightedAverage => {
+                    // Amount-weighted mean of the two stake times, expressed
+                    // as an offset so it stays in u64 and rounds towards now.
+                    let elapsed = now.saturating_sub(stake_entry.last_staked_at).max(0) as u64;
+                    let total = checked_add(stake_entry.amount, received)?;
+                    let shift = mul_div_u64(elapsed, received, total, Rounding::Up)?;
+                    stake_entry.last_staked_at + shift as i64
+                }
+            };
+            // A top-up can extend the lock but never shorten it.
+            stake_entry.min_stake_seconds = stake_entry.min_stake_seconds.max(min_stake_seconds);
+        }
+
+        stake_pool.remove_reward_weight(stake_entry.reward_weight())?;
+        stake_entry.apr = tier.apr_bps as u64;
+        stake_entry.amount = checked_add(stake_entry.amount, received)?;
+        stake_entry.refresh_effective_stake(stake_pool, now)?;
+        stake_pool.add_reward_weight(stake_entry.reward_weight())?;
+        stake_pool.total_staked = checked_add(stake_pool.total_staked, received)?;
+        stake_pool.ensure_funded(now)?;
+
+        emit!(events::Staked {
+            pool: stake_pool.key(),
+            stake_entry: stake_entry.key(),
+            staker: ctx.accounts.payer.key(),
+            amount: received,
+            entry_amount: stake_entry.amount,
+            total_staked: stake_pool.total_staked,
+        });
         Ok(())
     }
 
@@ -135,40 +487,76 @@ mod dyme_staking {
         let stake_entry = &mut ctx.accounts.stake_entry;
         let pool = &ctx.accounts.stake_pool.key();
         let stake_pool = &mut ctx.accounts.stake_pool;
-        let payer = &ctx.accounts.payer.key();
+        let owner = stake_entry.owner;
         let stake_mint = &ctx.accounts.stake_mint.key();
+        let decimals = ctx.accounts.stake_mint.decimals;
+        let reward_decimals = ctx.accounts.reward_mint.decimals;
+        let payer_token_info = ctx.accounts.payer_token_account.to_account_info();
+        let now = Clock::get()?.unix_timestamp;
 
         // if !stake_pool.is_active {
         //     return err!(errors::ErrorCode::PoolFrozen);
         // }
 
-        if stake_entry.amount <= 0 {
+        if stake_entry.amount == 0 {
             return err!(errors::ErrorCode::NoTokenStaked);
         }
 
+        if ix.amount == 0 {
+            return err!(errors::ErrorCode::InvalidAmount);
+        }
+
+        if ix.amount > stake_entry.amount {
+            return err!(errors::ErrorCode::InsufficientStakedAmount);
+        }
+
+        stake_pool.update_rewards(now)?;
+        stake_entry.settle_rewards(stake_pool)?;
+        // Stream rewards stay pending and are paid by `claim_token`.
+        stake_entry.settle_reward_streams(stake_pool, ctx.remaining_accounts, now)?;
+
+        // Only the withdrawn share of the pending rewards is settled here;
+        // the rest stays pending on the remaining stake.
+        let reward_share = mul_div_u64(
+            stake_entry.pending_rewards,
+            ix.amount,
+            stake_entry.amount,
+            Rounding::Down,
+        )?;
+        stake_entry.pending_rewards = checked_sub(stake_entry.pending_rewards, reward_share)?;
+
         let seeds = &[
             STAKE_ENTRY_PREFIX.as_bytes(),
             pool.as_ref(),
             stake_mint.as_ref(),
-            payer.as_ref(),
+            owner.as_ref(),
             &[stake_entry.bump],
         ];
 
         let signer_seeds = &[&seeds[..]];
+        let fee_config = stake_pool.fee_config;
+
+        let is_early_exit = stake_pool.is_active && stake_entry.is_locked(now);
+        let (penalty, admin_fee, unstake_fee, reward, received) = if is_early_exit {
+            // Exiting before the lock expires forfeits the withdrawn share
+            // of the rewards.
+            stake_pool.release_rewards(reward_share);
+
+            let deduction = apply_bps(
+                ix.amount,
+                fee_config.early_unstake_penalty_bps,
+                Rounding::Up,
+            )?;
+            let remaining_amount = checked_sub(ix.amount, deduction)?;
 
-        if stake_pool.is_active == true
-            && stake_entry.min_stake_seconds.is_some()
-            && stake_entry.min_stake_seconds.unwrap() > 0
-            && ((Clock::get().unwrap().unix_timestamp - stake_entry.last_staked_at) as u32)
-                < stake_entry.min_stake_seconds.unwrap()
-        {
-            let deduction = ix.amount * 30 / 100;
-            let remaining_amount = ix.amount - deduction;
-
-            let two_percent = deduction * 2 / 100;
-            let remaining_penalty = deduction - two_percent;
+            let admin_share = apply_bps(
+                deduction,
+                fee_config.penalty_admin_share_bps,
+                Rounding::Down,
+            )?;
+            let remaining_penalty = checked_sub(deduction, admin_share)?;
 
-            // Transfer to super admin 2%
+            // Transfer the super admin's share of the penalty
             let deduction_accounts = TransferChecked {
                 from: ctx.accounts.entry_token_account.to_account_info(),
                 to: ctx.accounts.super_admin_token_account.to_account_info(),
@@ -182,13 +570,9 @@ mod dyme_staking {
                 signer_seeds,
             );
 
-            transfer_checked(
-                deduction_ctx,
-                two_percent,
-                stake_pool.default_multiplier as u8,
-            )?;
+            transfer_checked(deduction_ctx, admin_share, decimals)?;
 
-            // Transfer to pool owner 98%
+            // Transfer the rest of the penalty to the pool owner
             let pool_owner_transfer = TransferChecked {
                 from: ctx.accounts.entry_token_account.to_account_info(),
                 to: ctx.accounts.pool_owner_token_account.to_account_info(),
@@ -202,13 +586,10 @@ mod dyme_staking {
                 signer_seeds,
             );
 
-            transfer_checked(
-                pool_owner_transfer_ctx,
-                remaining_penalty,
-                stake_pool.default_multiplier as u8,
-            )?;
+            transfer_checked(pool_owner_transfer_ctx, remaining_penalty, decimals)?;
 
             // Transfer to staker after deduction
+            let balance_before = token_amount(&payer_token_info)?;
             let accounts = TransferChecked {
                 from: ctx.accounts.entry_token_account.to_account_info(),
                 to: ctx.accounts.payer_token_account.to_account_info(),
@@ -222,18 +603,22 @@ mod dyme_staking {
                 signer_seeds,
             );
 
-            transfer_checked(ctx, remaining_amount, stake_pool.default_multiplier as u8)?;
+            transfer_checked(ctx, remaining_amount, decimals)?;
+            let received = checked_sub(token_amount(&payer_token_info)?, balance_before)?;
+
+            (deduction, admin_share, 0, 0, received)
         } else {
-            let unstake_fee = ix.amount * 1 / 100;
-            let amount = ix.amount - unstake_fee;
-            let mut unstake_amount: u64 = 0;
+            let unstake_fee = apply_bps(ix.amount, fee_config.unstake_fee_bps, Rounding::Up)?;
+            let amount = checked_sub(ix.amount, unstake_fee)?;
+            let unstake_amount: u64;
+            let mut frozen_fee = 0;
 
-            // 2% on freeze transfer
-            if stake_pool.is_active == false {
-                let deduction = unstake_fee * 30 / 100;
-                let two_percent = deduction * 2 / 100;
+            // Super admin cut on frozen pools, taken on top of the unstake fee
+            if !stake_pool.is_active {
+                frozen_fee =
+                    apply_bps(unstake_fee, fee_config.frozen_admin_share_bps, Rounding::Up)?;
 
-                unstake_amount = amount - two_percent;
+                unstake_amount = checked_sub(amount, frozen_fee)?;
 
                 let freeze_accounts = TransferChecked {
                     from: ctx.accounts.entry_token_account.to_account_info(),
@@ -248,40 +633,43 @@ mod dyme_staking {
                     signer_seeds,
                 );
 
-                transfer_checked(freeze_ctx, two_percent, stake_pool.default_multiplier as u8)?;
+                transfer_checked(freeze_ctx, frozen_fee, decimals)?;
             } else {
-                unstake_amount = ix.amount - unstake_fee;
+                unstake_amount = amount;
             }
 
-            let pool_apr_amount = unstake_amount * stake_pool.apr / 10000;
-            let stake_apr_amount = pool_apr_amount * stake_entry.apr / 10000;
+            // An underfunded vault must not block the principal; whatever
+            // it cannot cover is kept as an IOU on the entry.
+            let reward_amount = stake_entry.pay_rewards(
+                stake_pool,
+                reward_share,
+                ctx.accounts.reward_vault.amount,
+            )?;
 
-            let pool_seeds = &[
-                STAKE_POOL_PREFIX.as_bytes(),
-                stake_pool.identifier.as_ref(),
-                &[stake_pool.bump],
-            ];
+            if reward_amount > 0 {
+                let pool_seeds = &[
+                    STAKE_POOL_PREFIX.as_bytes(),
+                    stake_pool.identifier.as_ref(),
+                    &[stake_pool.bump],
+                ];
 
-            let pool_signer_seeds = &[&pool_seeds[..]];
+                let pool_signer_seeds = &[&pool_seeds[..]];
 
-            let pool_accounts = TransferChecked {
-                from: ctx.accounts.pool_token_account.to_account_info(),
-                to: ctx.accounts.payer_token_account.to_account_info(),
-                authority: stake_pool.to_account_info(),
-                mint: ctx.accounts.stake_mint.to_account_info(),
-            };
+                let pool_accounts = TransferChecked {
+                    from: ctx.accounts.reward_vault.to_account_info(),
+                    to: ctx.accounts.payer_reward_token_account.to_account_info(),
+                    authority: stake_pool.to_account_info(),
+                    mint: ctx.accounts.reward_mint.to_account_info(),
+                };
 
-            let pool_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                pool_accounts,
-                pool_signer_seeds,
-            );
+                let pool_ctx = CpiContext::new_with_signer(
+                    ctx.accounts.reward_token_program.to_account_info(),
+                    pool_accounts,
+                    pool_signer_seeds,
+                );
 
-            transfer_checked(
-                pool_ctx,
-                stake_apr_amount,
-                stake_pool.default_multiplier as u8,
-            )?;
+                transfer_checked(pool_ctx, reward_amount, reward_decimals)?;
+            }
 
             let unstake_fee_accounts = TransferChecked {
                 from: ctx.accounts.entry_token_account.to_account_info(),
@@ -296,12 +684,9 @@ mod dyme_staking {
                 signer_seeds,
             );
 
-            transfer_checked(
-                unstake_fee_ctx,
-                unstake_fee,
-                stake_pool.default_multiplier as u8,
-            )?;
+            transfer_checked(unstake_fee_ctx, unstake_fee, decimals)?;
 
+            let balance_before = token_amount(&payer_token_info)?;
             let accounts = TransferChecked {
                 from: ctx.accounts.entry_token_account.to_account_info(),
                 to: ctx.accounts.payer_token_account.to_account_info(),
@@ -315,38 +700,74 @@ mod dyme_staking {
                 signer_seeds,
             );
 
-            transfer_checked(ctx, unstake_amount, stake_pool.default_multiplier as u8)?;
-        }
+            transfer_checked(ctx, unstake_amount, decimals)?;
+            let received = checked_sub(token_amount(&payer_token_info)?, balance_before)?;
+
+            (0, frozen_fee, unstake_fee, reward_amount, received)
+        };
 
-        stake_entry.amount = stake_entry.amount - ix.amount;
-        stake_pool.total_staked = stake_pool.total_staked - ix.amount;
-        if stake_entry.amount <= 0 {
-            stake_pool.total_stakers = stake_pool.total_stakers.checked_sub(1).expect("Sub error");
+        stake_pool.remove_reward_weight(stake_entry.reward_weight())?;
+        stake_entry.amount = checked_sub(stake_entry.amount, ix.amount)?;
+        stake_entry.refresh_effective_stake(stake_pool, now)?;
+        stake_pool.add_reward_weight(stake_entry.reward_weight())?;
+        stake_pool.total_staked = checked_sub(stake_pool.total_staked, ix.amount)?;
+        if stake_entry.amount == 0 {
+            stake_pool.total_stakers = stake_pool
+                .total_stakers
+                .checked_sub(1)
+                .ok_or(errors::ErrorCode::MathOverflow)?;
         }
+
+        emit!(events::Unstaked {
+            pool: stake_pool.key(),
+            stake_entry: stake_entry.key(),
+            staker: ctx.accounts.payer.key(),
+            amount: ix.amount,
+            penalty,
+            admin_fee,
+            unstake_fee,
+            reward,
+            received,
+        });
         Ok(())
     }
 
-    pub fn claim_token(ctx: Context<ClaimCtx>, ix: UnstakeIx) -> Result<()> {
+    /// Pays the pool rewards and every reward stream. For each of the
+    /// pool's streams, in index order, `remaining_accounts` holds the
+    /// stream, its vault, its mint and the owner's token account.
+    pub fn claim_token<'info>(ctx: Context<'_, '_, '_, 'info, ClaimCtx<'info>>) -> Result<()> {
         let stake_entry = &mut ctx.accounts.stake_entry;
         let stake_pool = &mut ctx.accounts.stake_pool;
+        let reward_decimals = ctx.accounts.reward_mint.decimals;
+        let now = Clock::get()?.unix_timestamp;
 
         if !stake_pool.is_active {
             return err!(errors::ErrorCode::PoolFrozen);
         }
 
-        if stake_entry.amount <= 0 {
+        // A fully unstaked entry can still come back for an IOU or stream
+        // rewards.
+        if stake_entry.amount == 0
+            && stake_entry.reward_iou == 0
+            && stake_entry.stream_pending_rewards.iter().all(|&r| r == 0)
+        {
             return err!(errors::ErrorCode::NoTokenStaked);
         }
 
-        if stake_entry.min_stake_seconds.is_some()
-            && stake_entry.min_stake_seconds.unwrap() > 0
-            && ((Clock::get().unwrap().unix_timestamp - stake_entry.last_staked_at) as u32)
-                < stake_entry.min_stake_seconds.unwrap()
-        {
+        if stake_entry.is_locked(now) {
             return err!(errors::ErrorCode::MinStakeSecondsNotSatisfied);
         }
-        let pool_apr_amount = ix.amount * stake_pool.apr / 10000;
-        let stake_apr_amount = pool_apr_amount * stake_entry.apr / 10000;
+
+        stake_pool.update_rewards(now)?;
+        stake_entry.settle_rewards(stake_pool)?;
+
+        let pending_rewards = stake_entry.pending_rewards;
+        stake_entry.pending_rewards = 0;
+        let reward_amount = stake_entry.pay_rewards(
+            stake_pool,
+            pending_rewards,
+            ctx.accounts.reward_vault.amount,
+        )?;
 
         let pool_seeds = &[
             STAKE_POOL_PREFIX.as_bytes(),
@@ -357,172 +778,1686 @@ mod dyme_staking {
         let pool_signer_seeds = &[&pool_seeds[..]];
 
         let pool_accounts = TransferChecked {
-            from: ctx.accounts.pool_token_account.to_account_info(),
-            to: ctx.accounts.payer_token_account.to_account_info(),
+            from: ctx.accounts.reward_vault.to_account_info(),
+            to: ctx.accounts.owner_reward_token_account.to_account_info(),
             authority: stake_pool.to_account_info(),
-            mint: ctx.accounts.stake_mint.to_account_info(),
+            mint: ctx.accounts.reward_mint.to_account_info(),
         };
 
         let pool_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.reward_token_program.to_account_info(),
             pool_accounts,
             pool_signer_seeds,
         );
 
-        transfer_checked(
-            pool_ctx,
-            stake_apr_amount,
-            stake_pool.default_multiplier as u8,
-        )?;
+        transfer_checked(pool_ctx, reward_amount, reward_decimals)?;
+
+        let pool = stake_pool.key();
+        let stream_count = stake_pool.reward_stream_count as usize;
+        let stream_accounts = ctx
+            .remaining_accounts
+            .get(..stream_count * 4)
+            .ok_or(errors::ErrorCode::InvalidRewardStream)?;
+        let mut stream_paid = 0;
+
+        for (index, accounts) in stream_accounts.chunks_exact(4).enumerate() {
+            let (stream_info, vault_info, mint_info, destination_info) =
+                (&accounts[0], &accounts[1], &accounts[2], &accounts[3]);
+
+            let mut stream = RewardStream::load(stream_info, pool, index)?;
+            stream.update(now, stake_pool.total_reward_weight)?;
+            stake_entry.settle_stream(index, &mut stream)?;
+
+            if vault_info.key() != stream.reward_vault || mint_info.key() != stream.reward_mint {
+                return err!(errors::ErrorCode::InvalidRewardStream);
+            }
+            let vault = TokenAccount::try_deserialize(&mut &vault_info.try_borrow_data()?[..])?;
+            let destination =
+                TokenAccount::try_deserialize(&mut &destination_info.try_borrow_data()?[..])?;
+            if destination.owner != stake_entry.owner || destination.mint != stream.reward_mint {
+                return err!(errors::ErrorCode::InvalidRewardStream);
+            }
+
+            let amount = stake_entry.stream_pending_rewards[index].min(vault.amount);
+            if amount > 0 {
+                let token_program = if *vault_info.owner == ctx.accounts.token_program.key() {
+                    ctx.accounts.token_program.to_account_info()
+                } else if *vault_info.owner == ctx.accounts.reward_token_program.key() {
+                    ctx.accounts.reward_token_program.to_account_info()
+                } else {
+                    return err!(errors::ErrorCode::InvalidRewardStream);
+                };
+                let decimals =
+                    Mint::try_deserialize(&mut &mint_info.try_borrow_data()?[..])?.decimals;
+
+                let index_seed = [stream.index];
+                let stream_seeds = &[
+                    REWARD_STREAM_PREFIX.as_bytes(),
+                    pool.as_ref(),
+                    &index_seed,
+                    &[stream.bump],
+                ];
+                let stream_signer_seeds = &[&stream_seeds[..]];
+
+                let transfer_accounts = TransferChecked {
+                    from: vault_info.clone(),
+                    to: destination_info.clone(),
+                    authority: stream_info.clone(),
+                    mint: mint_info.clone(),
+                };
+                let stream_ctx = CpiContext::new_with_signer(
+                    token_program,
+                    transfer_accounts,
+                    stream_signer_seeds,
+                );
+                transfer_checked(stream_ctx, amount, decimals)?;
+            }
+
+            // Anything the vault could not cover stays pending.
+            stake_entry.stream_pending_rewards[index] -= amount;
+            stream.total_paid = checked_add(stream.total_paid, amount)?;
+            stream.release_rewards(amount);
+            stream.store(stream_info)?;
+            stream_paid = checked_add(stream_paid, amount)?;
+
+            emit!(events::StreamRewardsClaimed {
+                pool,
+                reward_stream: stream_info.key(),
+                stake_entry: stake_entry.key(),
+                staker: stake_entry.owner,
+                amount,
+            });
+        }
+
+        if reward_amount == 0 && stream_paid == 0 && stake_entry.reward_iou > 0 {
+            return err!(errors::ErrorCode::InsufficientRewards);
+        }
+
+        // Every stream is settled by now, so the weight can change.
+        stake_entry.refresh_boost(stake_pool, now)?;
 
-        stake_entry.last_staked_at = Clock::get().unwrap().unix_timestamp;
+        emit!(events::RewardsClaimed {
+            pool,
+            stake_entry: stake_entry.key(),
+            staker: stake_entry.owner,
+            amount: reward_amount,
+            reward_iou: stake_entry.reward_iou,
+        });
         Ok(())
     }
 
-    pub fn freeze_pool(ctx: Context<FreezePoolCtx>) -> Result<()> {
+    /// Adds a reward stream approved by the pool authority. The payer, who
+    /// may be a partner, funds it and becomes the stream's authority.
+    pub fn add_reward_stream(
+        ctx: Context<AddRewardStreamCtx>,
+        ix: AddRewardStreamIx,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        if ix.rate_per_second == 0 || ix.end_time <= now {
+            return err!(errors::ErrorCode::InvalidRewardStream);
+        }
+
         let stake_pool = &mut ctx.accounts.stake_pool;
-        stake_pool.is_active = false;
+        if stake_pool.reward_stream_count as usize >= MAX_REWARD_STREAMS {
+            return err!(errors::ErrorCode::TooManyRewardStreams);
+        }
+
+        let accounts = TransferChecked {
+            from: ctx.accounts.payer_token_account.to_account_info(),
+            to: ctx.accounts.reward_vault.to_account_info(),
+            authority: ctx.accounts.payer.to_account_info(),
+            mint: ctx.accounts.reward_mint.to_account_info(),
+        };
+        let transfer_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), accounts);
+
+        let vault_before = ctx.accounts.reward_vault.amount;
+        transfer_checked(transfer_ctx, ix.amount, ctx.accounts.reward_mint.decimals)?;
+        ctx.accounts.reward_vault.reload()?;
+        let received = checked_sub(ctx.accounts.reward_vault.amount, vault_before)?;
+
+        let reward_stream = &mut ctx.accounts.reward_stream;
+        reward_stream.set_inner(RewardStream {
+            bump: ctx.bumps.reward_stream,
+            pool: stake_pool.key(),
+            index: stake_pool.reward_stream_count,
+            reward_mint: ctx.accounts.reward_mint.key(),
+            reward_vault: ctx.accounts.reward_vault.key(),
+            rate_per_second: ix.rate_per_second,
+            end_time: ix.end_time,
+            reward_per_weight_stored: 0,
+            last_update: now,
+            total_funded: received,
+            total_paid: 0,
+            authority: ctx.accounts.payer.key(),
+            total_rewards_owed: 0,
+            total_rewards_pending: 0,
+        });
+        stake_pool.reward_stream_count += 1;
+
+        emit!(events::RewardStreamAdded {
+            pool: stake_pool.key(),
+            reward_stream: reward_stream.key(),
+            reward_mint: reward_stream.reward_mint,
+            authority: reward_stream.authority,
+            rate_per_second: ix.rate_per_second,
+            end_time: ix.end_time,
+            amount: received,
+        });
         Ok(())
     }
 
-    pub fn unfreeze_pool(ctx: Context<UnfreezePoolCtx>) -> Result<()> {
-        let stake_pool = &mut ctx.accounts.stake_pool;
-        stake_pool.is_active = true;
+    /// Sends the stream authority whatever the stream's vault holds beyond
+    /// what it owes stakers and will still emit until `end_time`.
+    pub fn withdraw_stream_excess(ctx: Context<WithdrawStreamExcessCtx>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let reward_stream = &mut ctx.accounts.reward_stream;
+        reward_stream.update(now, ctx.accounts.stake_pool.total_reward_weight)?;
+
+        let still_emitting = math::checked_mul(
+            reward_stream.rate_per_second as u128,
+            reward_stream.end_time.saturating_sub(now).max(0) as u128,
+        )?;
+        let reserved = still_emitting
+            .checked_add(reward_stream.total_rewards_owed as u128)
+            .ok_or(errors::ErrorCode::MathOverflow)?;
+        let excess = to_u64((ctx.accounts.reward_vault.amount as u128).saturating_sub(reserved))?;
+        if excess == 0 {
+            return err!(errors::ErrorCode::NoExcessRewards);
+        }
+
+        let pool = ctx.accounts.stake_pool.key();
+        let index_seed = [reward_stream.index];
+        let stream_seeds = &[
+            REWARD_STREAM_PREFIX.as_bytes(),
+            pool.as_ref(),
+            &index_seed,
+            &[reward_stream.bump],
+        ];
+        let stream_signer_seeds = &[&stream_seeds[..]];
+
+        let accounts = TransferChecked {
+            from: ctx.accounts.reward_vault.to_account_info(),
+            to: ctx.accounts.authority_token_account.to_account_info(),
+            authority: reward_stream.to_account_info(),
+            mint: ctx.accounts.reward_mint.to_account_info(),
+        };
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            accounts,
+            stream_signer_seeds,
+        );
+        transfer_checked(transfer_ctx, excess, ctx.accounts.reward_mint.decimals)?;
+        reward_stream.total_funded = reward_stream.total_funded.saturating_sub(excess);
+
+        emit!(events::StreamExcessWithdrawn {
+            pool,
+            reward_stream: reward_stream.key(),
+            authority: ctx.accounts.payer.key(),
+            amount: excess,
+        });
         Ok(())
     }
 
-}
+    /// Closes the pool's most recently added stream once nothing is staked
+    /// and stakers have claimed everything it credited them. What is left
+    /// in its vault goes back to the stream authority. Streams close last
+    /// to first.
+    pub fn close_reward_stream(ctx: Context<CloseRewardStreamCtx>) -> Result<()> {
+        let stake_pool = &mut ctx.accounts.stake_pool;
+        let reward_stream = &ctx.accounts.reward_stream;
 
-#[derive(Accounts)]
-#[instruction(ix: InitPoolIx)]
-pub struct InitPoolCtx<'info> {
-    #[account(
-        init,
-        payer = payer,
-        space = STAKE_POOL_DEFAULT_SIZE,
-        seeds = [STAKE_POOL_PREFIX.as_bytes(), ix.identifier.as_ref()],
-        bump
-    )]
-    stake_pool: Account<'info, StakePool>,
-    #[account(
-        init_if_needed,
-        payer = payer, 
-        associated_token::mint = mint, 
-        associated_token::authority = stake_pool
-    )]
-    pool_token_account: Account<'info, TokenAccount>,
-    mint: Account<'info, Mint>,
+        if reward_stream.index as usize + 1 != stake_pool.reward_stream_count as usize {
+            return err!(errors::ErrorCode::InvalidRewardStream);
+        }
 
-    #[account(mut, constraint = super_admin.key() == SUPER_ADMIN @ errors::ErrorCode::InvalidSuperAdmin)]
-    super_admin: UncheckedAccount<'info>,
+        if stake_pool.total_stakers > 0 {
+            return err!(errors::ErrorCode::PoolHasStakers);
+        }
 
-    #[account(mut)]
-    payer_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    payer: Signer<'info>,
-    token_program: Program<'info, Token>,
-    system_program: Program<'info, System>,
-    associated_token_program: Program<'info, AssociatedToken>,
-    rent: Sysvar<'info, Rent>,
-}
+        if reward_stream.total_rewards_pending > 0 {
+            return err!(errors::ErrorCode::UnpaidRewardsOutstanding);
+        }
 
-#[derive(Accounts)]
-pub struct UpdatePoolCtx<'info> {
-    #[account(mut, constraint = stake_pool.authority == payer.key())]
-    stake_pool: Account<'info, StakePool>,
-    #[account(mut)]
-    payer: Signer<'info>,
-    system_program: Program<'info, System>,
-}
+        let pool = stake_pool.key();
+        let index_seed = [reward_stream.index];
+        let stream_seeds = &[
+            REWARD_STREAM_PREFIX.as_bytes(),
+            pool.as_ref(),
+            &index_seed,
+            &[reward_stream.bump],
+        ];
+        let stream_signer_seeds = &[&stream_seeds[..]];
 
-#[derive(Accounts)]
-pub struct InitEntryCtx<'info> {
-    #[account(
-        init,
-        payer = payer,
-        space = 134,
-        seeds = [STAKE_ENTRY_PREFIX.as_bytes(), stake_pool.key().as_ref(), stake_mint.key().as_ref(), payer.key().as_ref()],
-        bump,
-    )]
-    stake_entry: Box<Account<'info, StakeEntry>>,
-    #[account(mut)]
-    stake_pool: Box<Account<'info, StakePool>>,
-    #[account(
-        init_if_needed,
-        payer = payer, 
-        associated_token::mint = stake_mint, 
-        associated_token::authority = stake_entry
-    )]
-    entry_token_account: Account<'info, TokenAccount>,
-    stake_mint: Box<Account<'info, Mint>>,
+        let remaining = ctx.accounts.reward_vault.amount;
+        if remaining > 0 {
+            let accounts = TransferChecked {
+                from: ctx.accounts.reward_vault.to_account_info(),
+                to: ctx.accounts.authority_token_account.to_account_info(),
+                authority: reward_stream.to_account_info(),
+                mint: ctx.accounts.reward_mint.to_account_info(),
+            };
+            let transfer_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                accounts,
+                stream_signer_seeds,
+            );
+            transfer_checked(transfer_ctx, remaining, ctx.accounts.reward_mint.decimals)?;
+        }
 
-    #[account(mut)]
-    payer: Signer<'info>,
-    token_program: Program<'info, Token>,
-    system_program: Program<'info, System>,
-    associated_token_program: Program<'info, AssociatedToken>,
-    rent: Sysvar<'info, Rent>,
-}
+        let accounts = CloseAccount {
+            account: ctx.accounts.reward_vault.to_account_info(),
+            destination: ctx.accounts.payer.to_account_info(),
+            authority: reward_stream.to_account_info(),
+        };
+        let close_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            accounts,
+            stream_signer_seeds,
+        );
+        close_account(close_ctx)?;
 
-#[derive(Accounts)]
-pub struct InitStakeCtx<'info> {
-    #[account(mut)]
-    stake_entry: Box<Account<'info, StakeEntry>>,
-    #[account(mut)]
-    stake_pool: Box<Account<'info, StakePool>>,
-    #[account(mut)]
-    entry_token_account: Account<'info, TokenAccount>,
+        stake_pool.reward_stream_count -= 1;
 
-    #[account(mut)]
-    payer_token_account: Account<'info, TokenAccount>,
-    token_program: Program<'info, Token>,
-    associated_token_program: Program<'info, AssociatedToken>,
-    rent: Sysvar<'info, Rent>,
+        emit!(events::RewardStreamClosed {
+            pool,
+            reward_stream: reward_stream.key(),
+            authority: ctx.accounts.payer.key(),
+            withdrawn: remaining,
+        });
+        Ok(())
+    }
 
-    #[account(mut)]
-    payer: Signer<'info>,
-    stake_mint: Account<'info, Mint>,
-    system_program: Program<'info, System>,
-}
+    /// Restakes the entry's rewards without touching its lock. Only for
+    /// pools that pay rewards in the stake mint. The pool's reward streams
+    /// must be passed in `remaining_accounts`, as for `stake_token`.
+    pub fn compound<'info>(ctx: Context<'_, '_, '_, 'info, CompoundCtx<'info>>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let stake_pool = &mut ctx.accounts.stake_pool;
+        stake_pool.ensure_compoundable(now)?;
 
-#[derive(Accounts)]
-pub struct UnstakeCtx<'info> {
-    #[account(mut, constraint = stake_entry.last_staker == payer.key() @ errors::ErrorCode::InvalidStaker)]
-    stake_entry: Box<Account<'info, StakeEntry>>,
-    #[account(mut)]
-    stake_pool: Box<Account<'info, StakePool>>,
+        if ctx.accounts.stake_entry.amount == 0 {
+            return err!(errors::ErrorCode::NoTokenStaked);
+        }
+
+        let accounts = CompoundAccounts {
+            stake_pool: stake_pool.to_account_info(),
+            reward_vault: ctx.accounts.reward_vault.to_account_info(),
+            entry_token_account: ctx.accounts.entry_token_account.to_account_info(),
+            stake_mint: ctx.accounts.stake_mint.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            tip_destination: None,
+        };
+        let stake_entry = &mut ctx.accounts.stake_entry;
+        let (compounded, _) = compound_position(
+            stake_pool,
+            stake_entry,
+            &accounts,
+            ctx.remaining_accounts,
+            ctx.accounts.stake_mint.decimals,
+            0,
+            now,
+        )?;
+
+        if compounded == 0 {
+            if stake_entry.reward_iou > 0 {
+                return err!(errors::ErrorCode::InsufficientRewards);
+            }
+            return err!(errors::ErrorCode::NoRewardsToCompound);
+        }
+
+        emit!(events::Compounded {
+            pool: stake_pool.key(),
+            stake_entry: stake_entry.key(),
+            staker: stake_entry.owner,
+            amount: compounded,
+            entry_amount: stake_entry.amount,
+        });
+        Ok(())
+    }
+
+    /// Compounds a batch of entries that opted into `auto_compound`. The
+    /// caller is paid `compound_tip_bps` of every compounded reward.
+    /// `remaining_accounts` holds the pool's reward streams in index order,
+    /// followed by a `[stake_entry, entry_token_account]` pair per entry.
+    /// Every entry whose lock has expired loses its boost; beyond that,
+    /// entries that did not opt in or have nothing to compound are skipped.
+    pub fn crank_compound<'info>(
+        ctx: Context<'_, '_, '_, 'info, CrankCompoundCtx<'info>>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let stake_pool = &mut ctx.accounts.stake_pool;
+        stake_pool.ensure_compoundable(now)?;
+
+        let pool = stake_pool.key();
+        let stream_count = stake_pool.reward_stream_count as usize;
+        if ctx.remaining_accounts.len() < stream_count {
+            return err!(errors::ErrorCode::InvalidRewardStream);
+        }
+        let (streams, entries) = ctx.remaining_accounts.split_at(stream_count);
+        if entries.len() % 2 != 0 {
+            return err!(errors::ErrorCode::InvalidStakeEntry);
+        }
+
+        let stake_mint = ctx.accounts.stake_mint.key();
+        let token_program = ctx.accounts.token_program.key();
+        let decimals = ctx.accounts.stake_mint.decimals;
+        let tip_bps = stake_pool.compound_tip_bps;
+        let mut total_tip: u64 = 0;
+        let mut compounded_entries: u32 = 0;
+
+        for pair in entries.chunks_exact(2) {
+            let (entry_info, entry_token_info) = (&pair[0], &pair[1]);
+
+            if *entry_info.owner != crate::ID || !entry_info.is_writable {
+                return err!(errors::ErrorCode::InvalidStakeEntry);
+            }
+            let mut stake_entry =
+                StakeEntry::try_deserialize(&mut &entry_info.try_borrow_data()?[..])?;
+            if stake_entry.pool != pool
+                || entry_token_info.key()
+                    != get_associated_token_address_with_program_id(
+                        &entry_info.key(),
+                        &stake_mint,
+                        &token_program,
+                    )
+            {
+                return err!(errors::ErrorCode::InvalidStakeEntry);
+            }
+
+            let boost_expired = stake_entry.has_expired_boost(now);
+            if boost_expired {
+                stake_entry.drop_expired_boost(stake_pool, streams, now)?;
+                emit!(events::BoostExpired {
+                    pool,
+                    stake_entry: entry_info.key(),
+                    effective_stake: stake_entry.effective_stake,
+                });
+            }
+
+            let (compounded, tip) = if stake_entry.auto_compound && stake_entry.amount > 0 {
+                let accounts = CompoundAccounts {
+                    stake_pool: stake_pool.to_account_info(),
+                    reward_vault: ctx.accounts.reward_vault.to_account_info(),
+                    entry_token_account: entry_token_info.clone(),
+                    stake_mint: ctx.accounts.stake_mint.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    tip_destination: Some(ctx.accounts.payer_token_account.to_account_info()),
+                };
+                compound_position(
+                    stake_pool,
+                    &mut stake_entry,
+                    &accounts,
+                    streams,
+                    decimals,
+                    tip_bps,
+                    now,
+                )?
+            } else if boost_expired {
+                (0, 0)
+            } else {
+                continue;
+            };
+
+            let mut data = entry_info.try_borrow_mut_data()?;
+            stake_entry.try_serialize(&mut &mut data[..])?;
+            drop(data);
+
+            if compounded == 0 {
+                continue;
+            }
+            total_tip = checked_add(total_tip, tip)?;
+            compounded_entries += 1;
+
+            emit!(events::Compounded {
+                pool,
+                stake_entry: entry_info.key(),
+                staker: stake_entry.owner,
+                amount: compounded,
+                entry_amount: stake_entry.amount,
+            });
+        }
+
+        emit!(events::CompoundCranked {
+            pool,
+            cranker: ctx.accounts.payer.key(),
+            entries: compounded_entries,
+            tip: total_tip,
+        });
+        Ok(())
+    }
+
+    /// Drops the boost of a position whose lock has expired, so it stops
+    /// earning at the boosted rate before its owner acts again. Anyone can
+    /// call it. `remaining_accounts` holds the pool's reward streams in
+    /// index order.
+    pub fn expire_boost<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExpireBoostCtx<'info>>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let stake_pool = &mut ctx.accounts.stake_pool;
+        let stake_entry = &mut ctx.accounts.stake_entry;
+
+        if !stake_entry.has_expired_boost(now) {
+            return err!(errors::ErrorCode::BoostNotExpired);
+        }
+        stake_entry.drop_expired_boost(stake_pool, ctx.remaining_accounts, now)?;
+
+        emit!(events::BoostExpired {
+            pool: stake_pool.key(),
+            stake_entry: stake_entry.key(),
+            effective_stake: stake_entry.effective_stake,
+        });
+        Ok(())
+    }
+
+    pub fn update_pool(ctx: Context<UpdatePoolCtx>, ix: UpdatePoolIx) -> Result<()> {
+        let stake_pool = &mut ctx.accounts.stake_pool;
+        let now = Clock::get()?.unix_timestamp;
+
+        // Checkpoint rewards under the current rate before it changes.
+        stake_pool.update_rewards(now)?;
+
+        if let Some(apr) = ix.apr {
+            if apr == 0 && stake_pool.reward_mode.uses_apr() {
+                return err!(errors::ErrorCode::InvalidApr);
+            }
+            stake_pool.apr = apr;
+        }
+
+        if let Some(end_date) = ix.end_date {
+            if end_date <= now {
+                return err!(errors::ErrorCode::InvalidEndDate);
+            }
+            stake_pool.end_date = Some(end_date);
+        }
+
+        if let Some(pool_name) = ix.pool_name {
+            if pool_name.is_empty() || pool_name.len() > MAX_POOL_NAME_LENGTH {
+                return err!(errors::ErrorCode::InvalidPoolName);
+            }
+            stake_pool.pool_name = pool_name;
+        }
+
+        if let Some(default_multiplier) = ix.default_multiplier {
+            if default_multiplier == 0 || default_multiplier > MAX_DEFAULT_MULTIPLIER {
+                return err!(errors::ErrorCode::InvalidMultiplier);
+            }
+            stake_pool.default_multiplier = default_multiplier;
+        }
+
+        if let Some(min_stake_seconds) = ix.min_stake_seconds {
+            stake_pool.min_stake_seconds = min_stake_seconds;
+        }
+
+        if let Some(lock_policy) = ix.lock_policy {
+            stake_pool.lock_policy = lock_policy;
+        }
+
+        if let Some(fee_config) = ix.fee_config {
+            fee_config.validate(&stake_pool.fee_limits)?;
+            stake_pool.fee_config = fee_config;
+        }
+
+        // Existing positions keep the terms they staked under until their
+        // next top-up.
+        if let Some(lock_tiers) = ix.lock_tiers {
+            LockTier::validate_tiers(&lock_tiers)?;
+            stake_pool.lock_tiers = lock_tiers;
+        }
+
+        if let Some(boost_curve) = ix.boost_curve {
+            boost_curve.validate()?;
+            stake_pool.boost_curve = boost_curve;
+        }
+
+        if let Some(compound_tip_bps) = ix.compound_tip_bps {
+            if compound_tip_bps > MAX_COMPOUND_TIP_BPS {
+                return err!(errors::ErrorCode::InvalidFeeConfig);
+            }
+            stake_pool.compound_tip_bps = compound_tip_bps;
+        }
+
+        let new_space = stake_pool.try_to_vec()?.len() + 8;
+        if new_space != stake_pool.to_account_info().data_len() {
+            resize_account(
+                &stake_pool.to_account_info(),
+                new_space,
+                &ctx.accounts.payer.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+            )?;
+        }
+
+        emit!(events::PoolUpdated {
+            pool: stake_pool.key(),
+            apr: stake_pool.apr,
+            end_date: stake_pool.end_date,
+            pool_name: stake_pool.pool_name.clone(),
+            default_multiplier: stake_pool.default_multiplier,
+            min_stake_seconds: stake_pool.min_stake_seconds,
+            lock_policy: stake_pool.lock_policy,
+            fee_config: stake_pool.fee_config,
+            lock_tiers: stake_pool.lock_tiers.clone(),
+            boost_curve: stake_pool.boost_curve.clone(),
+            compound_tip_bps: stake_pool.compound_tip_bps,
+        });
+        Ok(())
+    }
+
+    pub fn fund_pool(ctx: Context<FundPoolCtx>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return err!(errors::ErrorCode::InvalidAmount);
+        }
+
+        let accounts = TransferChecked {
+            from: ctx.accounts.payer_reward_token_account.to_account_info(),
+            to: ctx.accounts.reward_vault.to_account_info(),
+            authority: ctx.accounts.payer.to_account_info(),
+            mint: ctx.accounts.reward_mint.to_account_info(),
+        };
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.reward_token_program.to_account_info(),
+            accounts,
+        );
+
+        let vault_before = ctx.accounts.reward_vault.amount;
+        transfer_checked(transfer_ctx, amount, ctx.accounts.reward_mint.decimals)?;
+        ctx.accounts.reward_vault.reload()?;
+        let received = checked_sub(ctx.accounts.reward_vault.amount, vault_before)?;
+
+        let stake_pool = &mut ctx.accounts.stake_pool;
+        stake_pool.total_rewards_funded = checked_add(stake_pool.total_rewards_funded, received)?;
+
+        emit!(events::PoolFunded {
+            pool: stake_pool.key(),
+            depositor: ctx.accounts.payer.key(),
+            amount: received,
+            total_rewards_funded: stake_pool.total_rewards_funded,
+        });
+        Ok(())
+    }
+
+    pub fn withdraw_excess_rewards(ctx: Context<WithdrawExcessRewardsCtx>) -> Result<()> {
+        let stake_pool = &mut ctx.accounts.stake_pool;
+        let now = Clock::get()?.unix_timestamp;
+
+        // While stakers keep accruing, the obligations are still growing.
+        let has_ended = matches!(stake_pool.end_date, Some(end_date) if now >= end_date);
+        if !has_ended && stake_pool.total_stakers > 0 {
+            return err!(errors::ErrorCode::PoolStillAccruing);
+        }
+
+        stake_pool.update_rewards(now)?;
+
+        let excess = ctx
+            .accounts
+            .reward_vault
+            .amount
+            .saturating_sub(stake_pool.total_rewards_owed);
+        if excess == 0 {
+            return err!(errors::ErrorCode::NoExcessRewards);
+        }
+
+        let pool_seeds = &[
+            STAKE_POOL_PREFIX.as_bytes(),
+            stake_pool.identifier.as_ref(),
+            &[stake_pool.bump],
+        ];
+        let pool_signer_seeds = &[&pool_seeds[..]];
+
+        let accounts = TransferChecked {
+            from: ctx.accounts.reward_vault.to_account_info(),
+            to: ctx
+                .accounts
+                .authority_reward_token_account
+                .to_account_info(),
+            authority: stake_pool.to_account_info(),
+            mint: ctx.accounts.reward_mint.to_account_info(),
+        };
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.reward_token_program.to_account_info(),
+            accounts,
+            pool_signer_seeds,
+        );
+        transfer_checked(transfer_ctx, excess, ctx.accounts.reward_mint.decimals)?;
+        // Withdrawn funds no longer back future rewards.
+        stake_pool.total_rewards_funded = stake_pool.total_rewards_funded.saturating_sub(excess);
+
+        emit!(events::ExcessRewardsWithdrawn {
+            pool: stake_pool.key(),
+            authority: ctx.accounts.payer.key(),
+            amount: excess,
+        });
+        Ok(())
+    }
+
+    pub fn close_pool(ctx: Context<ClosePoolCtx>) -> Result<()> {
+        let stake_pool = &ctx.accounts.stake_pool;
+
+        if stake_pool.total_stakers > 0 {
+            return err!(errors::ErrorCode::PoolHasStakers);
+        }
+
+        if stake_pool.total_reward_ious > 0 {
+            return err!(errors::ErrorCode::UnpaidRewardsOutstanding);
+        }
+
+        // Stakers claim stream rewards through the pool, so every stream
+        // must be paid out and closed first.
+        if stake_pool.reward_stream_count > 0 {
+            return err!(errors::ErrorCode::RewardStreamsOutstanding);
+        }
+
+        let pool_seeds = &[
+            STAKE_POOL_PREFIX.as_bytes(),
+            stake_pool.identifier.as_ref(),
+            &[stake_pool.bump],
+        ];
+        let pool_signer_seeds = &[&pool_seeds[..]];
+
+        let remaining = ctx.accounts.reward_vault.amount;
+        if remaining > 0 {
+            let accounts = TransferChecked {
+                from: ctx.accounts.reward_vault.to_account_info(),
+                to: ctx
+                    .accounts
+                    .authority_reward_token_account
+                    .to_account_info(),
+                authority: stake_pool.to_account_info(),
+                mint: ctx.accounts.reward_mint.to_account_info(),
+            };
+            let transfer_ctx = CpiContext::new_with_signer(
+                ctx.accounts.reward_token_program.to_account_info(),
+                accounts,
+                pool_signer_seeds,
+            );
+            transfer_checked(transfer_ctx, remaining, ctx.accounts.reward_mint.decimals)?;
+        }
+
+        let accounts = CloseAccount {
+            account: ctx.accounts.reward_vault.to_account_info(),
+            destination: ctx.accounts.payer.to_account_info(),
+            authority: stake_pool.to_account_info(),
+        };
+        let close_ctx = CpiContext::new_with_signer(
+            ctx.accounts.reward_token_program.to_account_info(),
+            accounts,
+            pool_signer_seeds,
+        );
+        close_account(close_ctx)?;
+
+        emit!(events::PoolClosed {
+            pool: stake_pool.key(),
+            authority: ctx.accounts.payer.key(),
+            withdrawn: remaining,
+        });
+        Ok(())
+    }
+
+    pub fn set_emergency_withdraw(
+        ctx: Context<SetEmergencyWithdrawCtx>,
+        ix: SetEmergencyWithdrawIx,
+    ) -> Result<()> {
+        if ix.fee_bps > MAX_EMERGENCY_WITHDRAW_FEE_BPS {
+            return err!(errors::ErrorCode::InvalidFeeConfig);
+        }
+
+        let stake_pool = &mut ctx.accounts.stake_pool;
+        stake_pool.emergency_withdraw_enabled = ix.enabled;
+        stake_pool.emergency_withdraw_fee_bps = ix.fee_bps;
+
+        emit!(events::EmergencyWithdrawUpdated {
+            pool: stake_pool.key(),
+            enabled: ix.enabled,
+            fee_bps: ix.fee_bps,
+        });
+        Ok(())
+    }
+
+    /// Returns the whole principal, minus the emergency fee, without
+    /// touching the reward accounting beyond forfeiting the pending rewards
+    /// and any IOU. Lock periods and the pool's frozen state are ignored.
+    pub fn emergency_withdraw(ctx: Context<EmergencyWithdrawCtx>) -> Result<()> {
+        let stake_entry = &mut ctx.accounts.stake_entry;
+        let stake_pool = &mut ctx.accounts.stake_pool;
+        let decimals = ctx.accounts.stake_mint.decimals;
+
+        if !stake_pool.emergency_withdraw_enabled {
+            return err!(errors::ErrorCode::EmergencyWithdrawDisabled);
+        }
+
+        let amount = stake_entry.amount;
+        if amount == 0 {
+            return err!(errors::ErrorCode::NoTokenStaked);
+        }
+
+        let fee = apply_bps(amount, stake_pool.emergency_withdraw_fee_bps, Rounding::Up)?;
+        let withdrawn = checked_sub(amount, fee)?;
+
+        let pool = stake_pool.key();
+        let stake_mint = stake_entry.stake_mint;
+        let owner = stake_entry.owner;
+        let seeds = &[
+            STAKE_ENTRY_PREFIX.as_bytes(),
+            pool.as_ref(),
+            stake_mint.as_ref(),
+            owner.as_ref(),
+            &[stake_entry.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        if fee > 0 {
+            let fee_accounts = TransferChecked {
+                from: ctx.accounts.entry_token_account.to_account_info(),
+                to: ctx.accounts.pool_owner_token_account.to_account_info(),
+                authority: stake_entry.to_account_info(),
+                mint: ctx.accounts.stake_mint.to_account_info(),
+            };
+            let fee_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                fee_accounts,
+                signer_seeds,
+            );
+            transfer_checked(fee_ctx, fee, decimals)?;
+        }
+
+        let payer_token_info = ctx.accounts.payer_token_account.to_account_info();
+        let balance_before = token_amount(&payer_token_info)?;
+        let accounts = TransferChecked {
+            from: ctx.accounts.entry_token_account.to_account_info(),
+            to: ctx.accounts.payer_token_account.to_account_info(),
+            authority: stake_entry.to_account_info(),
+            mint: ctx.accounts.stake_mint.to_account_info(),
+        };
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            accounts,
+            signer_seeds,
+        );
+        transfer_checked(transfer_ctx, withdrawn, decimals)?;
+        let received = checked_sub(token_amount(&payer_token_info)?, balance_before)?;
+
+        // Neither the accumulator nor the reward streams are advanced, so a
+        // broken rate cannot block the exit. The position's share of
+        // anything accrued since the last checkpoint is never credited: in
+        // APR mode it was not yet added to `total_rewards_owed`, and fixed
+        // and stream emissions for that period go to the remaining stake on
+        // the next update.
+        stake_pool.release_rewards(checked_add(
+            stake_entry.pending_rewards,
+            stake_entry.reward_iou,
+        )?);
+        stake_pool.total_reward_ious =
+            checked_sub(stake_pool.total_reward_ious, stake_entry.reward_iou)?;
+        stake_entry.forfeit_reward_streams(stake_pool, ctx.remaining_accounts)?;
+        stake_pool.remove_reward_weight(stake_entry.reward_weight())?;
+        stake_pool.total_staked = checked_sub(stake_pool.total_staked, amount)?;
+        stake_pool.total_stakers = stake_pool
+            .total_stakers
+            .checked_sub(1)
+            .ok_or(errors::ErrorCode::MathOverflow)?;
+
+        stake_entry.amount = 0;
+        stake_entry.effective_stake = 0;
+        stake_entry.pending_rewards = 0;
+        stake_entry.reward_iou = 0;
+        stake_entry.reward_per_token_paid = stake_pool.reward_per_token_stored;
+
+        emit!(events::EmergencyWithdrawn {
+            pool,
+            stake_entry: stake_entry.key(),
+            staker: owner,
+            amount,
+            fee,
+            received,
+        });
+        Ok(())
+    }
+
+    pub fn set_fee_limits(ctx: Context<SetFeeLimitsCtx>, ix: FeeLimits) -> Result<()> {
+        ix.validate()?;
+
+        let stake_pool = &mut ctx.accounts.stake_pool;
+        stake_pool.fee_limits = ix;
+        stake_pool.fee_config = stake_pool.fee_config.clamp_to(&ix);
+
+        emit!(events::FeeLimitsUpdated {
+            pool: stake_pool.key(),
+        });
+        Ok(())
+    }
+
+    /// Rewrites a pool created by the first release into the current layout.
+    /// Its stake carries no reward weight until each of its entries has
+    /// gone through `migrate_stake_entry`.
+    pub fn migrate_pool(ctx: Context<MigratePoolCtx>) -> Result<()> {
+        let pool_info = ctx.accounts.stake_pool.to_account_info();
+
+        let legacy_pool = {
+            let data = pool_info.try_borrow_data()?;
+            if StakePool::try_deserialize(&mut &data[..]).is_ok() {
+                return err!(errors::ErrorCode::PoolAlreadyMigrated);
+            }
+            if data.len() < 8 || data[..8] != StakePool::DISCRIMINATOR {
+                return err!(errors::ErrorCode::InvalidPoolAccount);
+            }
+            LegacyStakePool::deserialize(&mut &data[8..])?
+        };
+
+        let expected_address = Pubkey::create_program_address(
+            &[
+                STAKE_POOL_PREFIX.as_bytes(),
+                legacy_pool.identifier.as_ref(),
+                &[legacy_pool.bump],
+            ],
+            ctx.program_id,
+        )
+        .map_err(|_| errors::ErrorCode::InvalidPoolAccount)?;
+        if expected_address != pool_info.key() {
+            return err!(errors::ErrorCode::InvalidPoolAccount);
+        }
+
+        let payer = ctx.accounts.payer.key();
+        if payer != legacy_pool.authority && payer != ctx.accounts.global_config.super_admin {
+            return err!(errors::ErrorCode::InvalidAdmin);
+        }
+
+        let legacy_vault = get_associated_token_address_with_program_id(
+            &pool_info.key(),
+            &legacy_pool.token_address,
+            &anchor_spl::token::ID,
+        );
+        if ctx.accounts.reward_vault.key() != legacy_vault {
+            return err!(errors::ErrorCode::InvalidPoolAccount);
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        // Legacy pools stored the mint decimals in default_multiplier, so
+        // they restart at a neutral 1x multiplier.
+        let migrated_pool = StakePool {
+            bump: legacy_pool.bump,
+            authority: legacy_pool.authority,
+            total_staked: legacy_pool.total_staked,
+            total_stakers: legacy_pool.total_stakers,
+            token_address: legacy_pool.token_address,
+            apr: legacy_pool.apr,
+            end_date: legacy_pool.end_date,
+            is_active: legacy_pool.is_active,
+            identifier: legacy_pool.identifier,
+            pool_name: legacy_pool.pool_name,
+            default_multiplier: MULTIPLIER_BASE,
+            created_at: legacy_pool.created_at,
+            min_stake_seconds: 0,
+            reward_per_token_stored: 0,
+            last_reward_update: now,
+            lock_policy: LockPolicy::Reset,
+            fee_config: FeeConfig::DEFAULT,
+            fee_limits: ctx.accounts.global_config.default_fee_limits,
+            // Legacy pools paid rewards in the stake mint, out of the pool's
+            // associated token account under the original token program.
+            reward_mint: legacy_pool.token_address,
+            reward_vault: legacy_vault,
+            total_reward_weight: 0,
+            total_rewards_owed: 0,
+            // Whatever the legacy vault holds is the pool's reward budget.
+            total_rewards_funded: ctx.accounts.reward_vault.amount,
+            total_rewards_paid: 0,
+            total_reward_ious: 0,
+            emergency_withdraw_enabled: false,
+            emergency_withdraw_fee_bps: 0,
+            // Entries of legacy pools earned the plain pool APR.
+            lock_tiers: vec![LockTier {
+                min_stake_seconds: 0,
+                apr_bps: BASIS_POINTS as u16,
+            }],
+            boost_curve: BoostCurve::None,
+            reward_mode: RewardMode::Apr,
+            reward_stream_count: 0,
+            compound_tip_bps: 0,
+        };
+
+        let new_space = migrated_pool.try_to_vec()?.len() + 8;
+        resize_account(
+            &pool_info,
+            new_space,
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        let mut data = pool_info.try_borrow_mut_data()?;
+        migrated_pool.try_serialize(&mut &mut data[..])?;
+
+        emit!(events::PoolMigrated {
+            pool: pool_info.key(),
+        });
+        Ok(())
+    }
+
+    /// Rewrites a stake entry created by the first release into the current
+    /// layout and adds its weight to the pool, which must be migrated
+    /// first. Legacy positions move to the pool's first lock tier, keep
+    /// their lock and earn from the pool's migration onwards. The pool's
+    /// reward streams must be passed in `remaining_accounts`, as for
+    /// `stake_token`.
+    pub fn migrate_stake_entry(ctx: Context<MigrateStakeEntryCtx>) -> Result<()> {
+        let entry_info = ctx.accounts.stake_entry.to_account_info();
+        let stake_pool = &mut ctx.accounts.stake_pool;
+
+        let legacy_entry = {
+            let data = entry_info.try_borrow_data()?;
+            if StakeEntry::try_deserialize(&mut &data[..]).is_ok() {
+                return err!(errors::ErrorCode::StakeEntryAlreadyMigrated);
+            }
+            if data.len() < 8 || data[..8] != StakeEntry::DISCRIMINATOR {
+                return err!(errors::ErrorCode::InvalidStakeEntry);
+            }
+            LegacyStakeEntry::deserialize(&mut &data[8..])?
+        };
+
+        // Entries that never staked have no `last_staker`, so the owner is
+        // checked against the entry address instead.
+        let owner = ctx.accounts.owner.key();
+        let expected_address = Pubkey::create_program_address(
+            &[
+                STAKE_ENTRY_PREFIX.as_bytes(),
+                legacy_entry.pool.as_ref(),
+                legacy_entry.stake_mint.as_ref(),
+                owner.as_ref(),
+                &[legacy_entry.bump],
+            ],
+            ctx.program_id,
+        )
+        .map_err(|_| errors::ErrorCode::InvalidStakeEntry)?;
+        if expected_address != entry_info.key() || legacy_entry.pool != stake_pool.key() {
+            return err!(errors::ErrorCode::InvalidStakeEntry);
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        stake_pool.update_rewards(now)?;
+
+        // The staker-chosen APR of the first release is dropped in favour
+        // of the pool's tiers.
+        let tier = stake_pool.lock_tiers[0];
+        let mut stake_entry = StakeEntry {
+            bump: legacy_entry.bump,
+            pool: legacy_entry.pool,
+            amount: legacy_entry.amount,
+            stake_mint: legacy_entry.stake_mint,
+            owner,
+            last_staked_at: legacy_entry.last_staked_at,
+            min_stake_seconds: legacy_entry.min_stake_seconds,
+            apr: tier.apr_bps as u64,
+            // `migrate_pool` restarts the accumulator at zero.
+            reward_per_token_paid: 0,
+            pending_rewards: 0,
+            delegate: None,
+            reward_iou: 0,
+            tier: 0,
+            effective_stake: 0,
+            stream_rewards_paid: [0; MAX_REWARD_STREAMS],
+            stream_pending_rewards: [0; MAX_REWARD_STREAMS],
+            auto_compound: false,
+        };
+        // Checkpoints the streams while the entry has no weight yet.
+        stake_entry.settle_reward_streams(stake_pool, ctx.remaining_accounts, now)?;
+        stake_entry.refresh_effective_stake(stake_pool, now)?;
+        stake_pool.add_reward_weight(stake_entry.reward_weight())?;
+        // The pool accrued nothing for this position while it waited, so
+        // what it earned since the pool migration becomes owed now.
+        stake_entry.settle_rewards(stake_pool)?;
+        stake_pool.total_rewards_owed =
+            checked_add(stake_pool.total_rewards_owed, stake_entry.pending_rewards)?;
+
+        // Sized like a fresh entry, so a delegate can still be set later.
+        resize_account(
+            &entry_info,
+            STAKE_ENTRY_SIZE,
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        let mut data = entry_info.try_borrow_mut_data()?;
+        stake_entry.try_serialize(&mut &mut data[..])?;
+
+        emit!(events::StakeEntryMigrated {
+            pool: stake_entry.pool,
+            stake_entry: entry_info.key(),
+            owner,
+        });
+        Ok(())
+    }
+
+    pub fn freeze_pool(ctx: Context<FreezePoolCtx>) -> Result<()> {
+        let stake_pool = &mut ctx.accounts.stake_pool;
+        stake_pool.is_active = false;
+
+        emit!(events::PoolFrozen {
+            pool: stake_pool.key(),
+            by: ctx.accounts.payer.key(),
+        });
+        Ok(())
+    }
+
+    pub fn unfreeze_pool(ctx: Context<UnfreezePoolCtx>) -> Result<()> {
+        let stake_pool = &mut ctx.accounts.stake_pool;
+        stake_pool.is_active = true;
+
+        emit!(events::PoolUnfrozen {
+            pool: stake_pool.key(),
+            by: ctx.accounts.payer.key(),
+        });
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitGlobalConfigCtx<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = GLOBAL_CONFIG_SIZE,
+        seeds = [GLOBAL_CONFIG_PREFIX.as_bytes()],
+        bump
+    )]
+    global_config: Account<'info, GlobalConfig>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    program: Program<'info, crate::program::DymeStaking>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(payer.key()) @ errors::ErrorCode::InvalidSuperAdmin)]
+    program_data: Account<'info, ProgramData>,
     #[account(mut)]
-    entry_token_account: Account<'info, TokenAccount>,
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetSuperAdminCtx<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_CONFIG_PREFIX.as_bytes()],
+        bump = global_config.bump,
+        constraint = global_config.super_admin == payer.key() @ errors::ErrorCode::InvalidSuperAdmin
+    )]
+    global_config: Account<'info, GlobalConfig>,
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptSuperAdminCtx<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_CONFIG_PREFIX.as_bytes()],
+        bump = global_config.bump,
+        constraint = global_config.pending_super_admin == Some(payer.key()) @ errors::ErrorCode::InvalidSuperAdmin
+    )]
+    global_config: Account<'info, GlobalConfig>,
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPlatformFeeCtx<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_CONFIG_PREFIX.as_bytes()],
+        bump = global_config.bump,
+        constraint = global_config.super_admin == payer.key() @ errors::ErrorCode::InvalidSuperAdmin
+    )]
+    global_config: Account<'info, GlobalConfig>,
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(ix: InitPoolIx)]
+pub struct InitPoolCtx<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = STAKE_POOL_DEFAULT_SIZE,
+        seeds = [STAKE_POOL_PREFIX.as_bytes(), ix.identifier.as_ref()],
+        bump
+    )]
+    stake_pool: Account<'info, StakePool>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = reward_mint,
+        associated_token::authority = stake_pool,
+        associated_token::token_program = reward_token_program
+    )]
+    reward_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = ix.token_address @ errors::ErrorCode::InvalidStakeMint)]
+    mint: InterfaceAccount<'info, Mint>,
+    reward_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(seeds = [GLOBAL_CONFIG_PREFIX.as_bytes()], bump = global_config.bump)]
+    global_config: Box<Account<'info, GlobalConfig>>,
+    /// CHECK: receives the platform fee, checked against the global config
+    #[account(mut, address = global_config.fee_recipient @ errors::ErrorCode::InvalidFeeRecipient)]
+    fee_recipient: UncheckedAccount<'info>,
+
     #[account(mut)]
-    pool_token_account: Account<'info, TokenAccount>,
-    stake_mint: Box<Account<'info, Mint>>,
+    payer_reward_token_account: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
-    payer_token_account: Account<'info, TokenAccount>,
+    payer: Signer<'info>,
+    reward_token_program: Interface<'info, TokenInterface>,
+    system_program: Program<'info, System>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePoolCtx<'info> {
+    #[account(mut, constraint = stake_pool.authority == payer.key() @ errors::ErrorCode::InvalidAdmin)]
+    stake_pool: Account<'info, StakePool>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitEntryCtx<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = STAKE_ENTRY_SIZE,
+        seeds = [STAKE_ENTRY_PREFIX.as_bytes(), stake_pool.key().as_ref(), stake_mint.key().as_ref(), payer.key().as_ref()],
+        bump,
+    )]
+    stake_entry: Box<Account<'info, StakeEntry>>,
+    #[account(mut)]
+    stake_pool: Box<Account<'info, StakePool>>,
+    #[account(address = stake_pool.token_address @ errors::ErrorCode::InvalidStakeMint)]
+    stake_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = stake_mint,
+        associated_token::authority = stake_entry,
+        associated_token::token_program = token_program
+    )]
+    entry_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    payer: Signer<'info>,
+    token_program: Interface<'info, TokenInterface>,
+    system_program: Program<'info, System>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct InitStakeCtx<'info> {
+    #[account(
+        mut,
+        seeds = [STAKE_ENTRY_PREFIX.as_bytes(), stake_pool.key().as_ref(), stake_pool.token_address.as_ref(), stake_entry.owner.as_ref()],
+        bump = stake_entry.bump,
+        constraint = stake_entry.owner == payer.key() @ errors::ErrorCode::InvalidStaker
+    )]
+    stake_entry: Box<Account<'info, StakeEntry>>,
+    #[account(mut)]
+    stake_pool: Box<Account<'info, StakePool>>,
+    #[account(address = stake_pool.token_address @ errors::ErrorCode::InvalidStakeMint)]
+    stake_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = stake_mint,
+        associated_token::authority = stake_entry,
+        associated_token::token_program = token_program
+    )]
+    entry_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    payer_token_account: InterfaceAccount<'info, TokenAccount>,
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    rent: Sysvar<'info, Rent>,
+
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeCtx<'info> {
+    #[account(
+        mut,
+        seeds = [STAKE_ENTRY_PREFIX.as_bytes(), stake_pool.key().as_ref(), stake_pool.token_address.as_ref(), stake_entry.owner.as_ref()],
+        bump = stake_entry.bump,
+        constraint = stake_entry.owner == payer.key() @ errors::ErrorCode::InvalidStaker
+    )]
+    stake_entry: Box<Account<'info, StakeEntry>>,
+    #[account(mut)]
+    stake_pool: Box<Account<'info, StakePool>>,
+    #[account(address = stake_pool.token_address @ errors::ErrorCode::InvalidStakeMint)]
+    stake_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = stake_mint,
+        associated_token::authority = stake_entry,
+        associated_token::token_program = token_program
+    )]
+    entry_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = stake_pool.reward_vault)]
+    reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = stake_pool.reward_mint)]
+    reward_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint = stake_mint,
+        token::authority = payer,
+        token::token_program = token_program
+    )]
+    payer_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = reward_mint,
+        token::authority = payer,
+        token::token_program = reward_token_program
+    )]
+    payer_reward_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(seeds = [GLOBAL_CONFIG_PREFIX.as_bytes()], bump = global_config.bump)]
+    global_config: Box<Account<'info, GlobalConfig>>,
+    /// CHECK: checked against the global config
+    #[account(address = global_config.super_admin @ errors::ErrorCode::InvalidSuperAdmin)]
     super_admin: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        associated_token::mint = stake_mint,
+        associated_token::authority = super_admin,
+        associated_token::token_program = token_program
+    )]
+    super_admin_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = stake_mint,
+        associated_token::authority = stake_pool.authority,
+        associated_token::token_program = token_program
+    )]
+    pool_owner_token_account: InterfaceAccount<'info, TokenAccount>,
+    token_program: Interface<'info, TokenInterface>,
+    reward_token_program: Interface<'info, TokenInterface>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimCtx<'info> {
+    #[account(
+        mut,
+        seeds = [STAKE_ENTRY_PREFIX.as_bytes(), stake_pool.key().as_ref(), stake_pool.token_address.as_ref(), stake_entry.owner.as_ref()],
+        bump = stake_entry.bump,
+        constraint = stake_entry.owner == payer.key() || stake_entry.delegate == Some(payer.key()) @ errors::ErrorCode::InvalidStaker
+    )]
+    stake_entry: Box<Account<'info, StakeEntry>>,
     #[account(mut)]
-    super_admin_token_account: Account<'info, TokenAccount>,
-    #[account(mut, associated_token::mint = stake_mint,  associated_token::authority = stake_pool.authority)]
-    pool_owner_token_account: Account<'info, TokenAccount>,
-    token_program: Program<'info, Token>,
+    stake_pool: Box<Account<'info, StakePool>>,
+    #[account(address = stake_pool.token_address @ errors::ErrorCode::InvalidStakeMint)]
+    stake_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = stake_mint,
+        associated_token::authority = stake_entry,
+        associated_token::token_program = token_program
+    )]
+    entry_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = stake_pool.reward_vault)]
+    reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = stake_pool.reward_mint)]
+    reward_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// Rewards always go to the owner, even when a delegate claims.
+    #[account(
+        mut,
+        token::mint = reward_mint,
+        token::authority = stake_entry.owner,
+        token::token_program = reward_token_program
+    )]
+    owner_reward_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    token_program: Interface<'info, TokenInterface>,
+    reward_token_program: Interface<'info, TokenInterface>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CompoundCtx<'info> {
+    #[account(
+        mut,
+        seeds = [STAKE_ENTRY_PREFIX.as_bytes(), stake_pool.key().as_ref(), stake_pool.token_address.as_ref(), stake_entry.owner.as_ref()],
+        bump = stake_entry.bump,
+        constraint = stake_entry.owner == payer.key() @ errors::ErrorCode::InvalidStaker
+    )]
+    stake_entry: Box<Account<'info, StakeEntry>>,
+    #[account(mut)]
+    stake_pool: Box<Account<'info, StakePool>>,
+    #[account(address = stake_pool.token_address @ errors::ErrorCode::InvalidStakeMint)]
+    stake_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = stake_mint,
+        associated_token::authority = stake_entry,
+        associated_token::token_program = token_program
+    )]
+    entry_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = stake_pool.reward_vault)]
+    reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    token_program: Interface<'info, TokenInterface>,
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CrankCompoundCtx<'info> {
+    #[account(mut)]
+    stake_pool: Box<Account<'info, StakePool>>,
+    #[account(address = stake_pool.token_address @ errors::ErrorCode::InvalidStakeMint)]
+    stake_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, address = stake_pool.reward_vault)]
+    reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Receives the crank tip.
+    #[account(
+        mut,
+        token::mint = stake_mint,
+        token::authority = payer,
+        token::token_program = token_program
+    )]
+    payer_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    token_program: Interface<'info, TokenInterface>,
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireBoostCtx<'info> {
+    #[account(
+        mut,
+        seeds = [STAKE_ENTRY_PREFIX.as_bytes(), stake_pool.key().as_ref(), stake_pool.token_address.as_ref(), stake_entry.owner.as_ref()],
+        bump = stake_entry.bump
+    )]
+    stake_entry: Box<Account<'info, StakeEntry>>,
+    #[account(mut)]
+    stake_pool: Box<Account<'info, StakePool>>,
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseStakeEntryCtx<'info> {
+    #[account(
+        mut,
+        seeds = [STAKE_ENTRY_PREFIX.as_bytes(), stake_entry.pool.as_ref(), stake_entry.stake_mint.as_ref(), stake_entry.owner.as_ref()],
+        bump = stake_entry.bump,
+        constraint = stake_entry.owner == payer.key() @ errors::ErrorCode::InvalidStaker,
+        close = payer
+    )]
+    stake_entry: Box<Account<'info, StakeEntry>>,
+    /// The pool is not required, so entries can still be closed after
+    /// `close_pool`. Writable so withheld transfer fees can be harvested.
+    #[account(mut, address = stake_entry.stake_mint @ errors::ErrorCode::InvalidStakeMint)]
+    stake_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = stake_mint,
+        associated_token::authority = stake_entry,
+        associated_token::token_program = token_program
+    )]
+    entry_token_account: InterfaceAccount<'info, TokenAccount>,
+    /// Receives any tokens left in the entry account.
+    #[account(
+        mut,
+        token::mint = stake_mint,
+        token::authority = payer,
+        token::token_program = token_program
+    )]
+    payer_token_account: InterfaceAccount<'info, TokenAccount>,
+    token_program: Interface<'info, TokenInterface>,
+    #[account(mut)]
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AddRewardStreamCtx<'info> {
+    #[account(mut, constraint = stake_pool.authority == pool_authority.key() @ errors::ErrorCode::InvalidAdmin)]
+    stake_pool: Box<Account<'info, StakePool>>,
+    pool_authority: Signer<'info>,
+    #[account(
+        init,
+        payer = payer,
+        space = REWARD_STREAM_SIZE,
+        seeds = [REWARD_STREAM_PREFIX.as_bytes(), stake_pool.key().as_ref(), &[stake_pool.reward_stream_count]],
+        bump
+    )]
+    reward_stream: Box<Account<'info, RewardStream>>,
+    reward_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = reward_mint,
+        associated_token::authority = reward_stream,
+        associated_token::token_program = token_program
+    )]
+    reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        token::mint = reward_mint,
+        token::authority = payer,
+        token::token_program = token_program
+    )]
+    payer_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    token_program: Interface<'info, TokenInterface>,
+    system_program: Program<'info, System>,
+    associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawStreamExcessCtx<'info> {
+    stake_pool: Box<Account<'info, StakePool>>,
+    #[account(
+        mut,
+        seeds = [REWARD_STREAM_PREFIX.as_bytes(), stake_pool.key().as_ref(), &[reward_stream.index]],
+        bump = reward_stream.bump,
+        constraint = reward_stream.authority == payer.key() @ errors::ErrorCode::InvalidAdmin
+    )]
+    reward_stream: Box<Account<'info, RewardStream>>,
+    #[account(mut, address = reward_stream.reward_vault)]
+    reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = reward_stream.reward_mint)]
+    reward_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint = reward_mint,
+        token::authority = payer,
+        token::token_program = token_program
+    )]
+    authority_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    token_program: Interface<'info, TokenInterface>,
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseRewardStreamCtx<'info> {
+    #[account(mut)]
+    stake_pool: Box<Account<'info, StakePool>>,
+    #[account(
+        mut,
+        seeds = [REWARD_STREAM_PREFIX.as_bytes(), stake_pool.key().as_ref(), &[reward_stream.index]],
+        bump = reward_stream.bump,
+        constraint = reward_stream.authority == payer.key() @ errors::ErrorCode::InvalidAdmin,
+        close = payer
+    )]
+    reward_stream: Box<Account<'info, RewardStream>>,
+    #[account(mut, address = reward_stream.reward_vault)]
+    reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = reward_stream.reward_mint)]
+    reward_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint = reward_mint,
+        token::authority = payer,
+        token::token_program = token_program
+    )]
+    authority_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    token_program: Interface<'info, TokenInterface>,
+    #[account(mut)]
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetAutoCompoundCtx<'info> {
+    #[account(mut, constraint = stake_entry.owner == payer.key() @ errors::ErrorCode::InvalidStaker)]
+    stake_entry: Box<Account<'info, StakeEntry>>,
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetDelegateCtx<'info> {
+    #[account(mut, constraint = stake_entry.owner == payer.key() @ errors::ErrorCode::InvalidStaker)]
+    stake_entry: Box<Account<'info, StakeEntry>>,
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FundPoolCtx<'info> {
+    #[account(mut)]
+    stake_pool: Box<Account<'info, StakePool>>,
+    #[account(mut, address = stake_pool.reward_vault)]
+    reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = stake_pool.reward_mint)]
+    reward_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint = reward_mint,
+        token::authority = payer,
+        token::token_program = reward_token_program
+    )]
+    payer_reward_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    reward_token_program: Interface<'info, TokenInterface>,
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawExcessRewardsCtx<'info> {
+    #[account(mut, constraint = stake_pool.authority == payer.key() @ errors::ErrorCode::InvalidAdmin)]
+    stake_pool: Box<Account<'info, StakePool>>,
+    #[account(mut, address = stake_pool.reward_vault)]
+    reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = stake_pool.reward_mint)]
+    reward_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint = reward_mint,
+        token::authority = payer,
+        token::token_program = reward_token_program
+    )]
+    authority_reward_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    reward_token_program: Interface<'info, TokenInterface>,
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePoolCtx<'info> {
+    #[account(
+        mut,
+        constraint = stake_pool.authority == payer.key() @ errors::ErrorCode::InvalidAdmin,
+        close = payer
+    )]
+    stake_pool: Box<Account<'info, StakePool>>,
+    #[account(mut, address = stake_pool.reward_vault)]
+    reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = stake_pool.reward_mint)]
+    reward_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint = reward_mint,
+        token::authority = payer,
+        token::token_program = reward_token_program
+    )]
+    authority_reward_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    reward_token_program: Interface<'info, TokenInterface>,
+    #[account(mut)]
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetEmergencyWithdrawCtx<'info> {
     #[account(mut)]
+    stake_pool: Account<'info, StakePool>,
+    #[account(seeds = [GLOBAL_CONFIG_PREFIX.as_bytes()], bump = global_config.bump)]
+    global_config: Account<'info, GlobalConfig>,
+    #[account(constraint = payer.key() == global_config.super_admin @ errors::ErrorCode::InvalidSuperAdmin)]
     payer: Signer<'info>,
-    system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ClaimCtx<'info> {
-    #[account(mut, constraint = stake_entry.last_staker == payer.key() @ errors::ErrorCode::InvalidStaker)]
+pub struct EmergencyWithdrawCtx<'info> {
+    #[account(
+        mut,
+        seeds = [STAKE_ENTRY_PREFIX.as_bytes(), stake_pool.key().as_ref(), stake_pool.token_address.as_ref(), stake_entry.owner.as_ref()],
+        bump = stake_entry.bump,
+        constraint = stake_entry.owner == payer.key() @ errors::ErrorCode::InvalidStaker
+    )]
     stake_entry: Box<Account<'info, StakeEntry>>,
     #[account(mut)]
     stake_pool: Box<Account<'info, StakePool>>,
+    #[account(address = stake_pool.token_address @ errors::ErrorCode::InvalidStakeMint)]
+    stake_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = stake_mint,
+        associated_token::authority = stake_entry,
+        associated_token::token_program = token_program
+    )]
+    entry_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = stake_mint,
+        token::authority = payer,
+        token::token_program = token_program
+    )]
+    payer_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = stake_mint,
+        associated_token::authority = stake_pool.authority,
+        associated_token::token_program = token_program
+    )]
+    pool_owner_token_account: InterfaceAccount<'info, TokenAccount>,
+    token_program: Interface<'info, TokenInterface>,
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetFeeLimitsCtx<'info> {
     #[account(mut)]
-    entry_token_account: Account<'info, TokenAccount>,
+    stake_pool: Account<'info, StakePool>,
+    #[account(seeds = [GLOBAL_CONFIG_PREFIX.as_bytes()], bump = global_config.bump)]
+    global_config: Account<'info, GlobalConfig>,
+    #[account(constraint = payer.key() == global_config.super_admin @ errors::ErrorCode::InvalidSuperAdmin)]
+    payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigratePoolCtx<'info> {
+    /// CHECK: may still hold the legacy StakePool layout, validated in the handler
+    #[account(mut, owner = crate::ID)]
+    stake_pool: UncheckedAccount<'info>,
+    /// The legacy reward token account, checked in the handler
+    reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(seeds = [GLOBAL_CONFIG_PREFIX.as_bytes()], bump = global_config.bump)]
+    global_config: Account<'info, GlobalConfig>,
     #[account(mut)]
-    pool_token_account: Account<'info, TokenAccount>,
-    stake_mint: Box<Account<'info, Mint>>,
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateStakeEntryCtx<'info> {
+    /// CHECK: may still hold the legacy StakeEntry layout, validated in the handler
+    #[account(mut, owner = crate::ID)]
+    stake_entry: UncheckedAccount<'info>,
     #[account(mut)]
-    payer_token_account: Account<'info, TokenAccount>,
-    token_program: Program<'info, Token>,
+    stake_pool: Box<Account<'info, StakePool>>,
+    /// CHECK: only used to derive the entry address
+    owner: UncheckedAccount<'info>,
     #[account(mut)]
     payer: Signer<'info>,
     system_program: Program<'info, System>,
@@ -530,8 +2465,10 @@ pub struct ClaimCtx<'info> {
 
 #[derive(Accounts)]
 pub struct FreezePoolCtx<'info> {
-    #[account(mut, constraint = stake_pool.authority==payer.key() || payer.key()==SUPER_ADMIN @ errors::ErrorCode::InvalidAdmin)]
+    #[account(mut, constraint = stake_pool.authority==payer.key() || payer.key()==global_config.super_admin @ errors::ErrorCode::InvalidAdmin)]
     stake_pool: Account<'info, StakePool>,
+    #[account(seeds = [GLOBAL_CONFIG_PREFIX.as_bytes()], bump = global_config.bump)]
+    global_config: Account<'info, GlobalConfig>,
     #[account(mut)]
     payer: Signer<'info>,
     system_program: Program<'info, System>,
@@ -539,13 +2476,50 @@ pub struct FreezePoolCtx<'info> {
 
 #[derive(Accounts)]
 pub struct UnfreezePoolCtx<'info> {
-    #[account(mut, constraint = stake_pool.authority==payer.key() || payer.key()==SUPER_ADMIN @ errors::ErrorCode::InvalidAdmin)]
+    #[account(mut, constraint = stake_pool.authority==payer.key() || payer.key()==global_config.super_admin @ errors::ErrorCode::InvalidAdmin)]
     stake_pool: Account<'info, StakePool>,
+    #[account(seeds = [GLOBAL_CONFIG_PREFIX.as_bytes()], bump = global_config.bump)]
+    global_config: Account<'info, GlobalConfig>,
     #[account(mut)]
     payer: Signer<'info>,
     system_program: Program<'info, System>,
 }
 
+#[account]
+pub struct GlobalConfig {
+    pub bump: u8,
+    pub super_admin: Pubkey,
+    pub pending_super_admin: Option<Pubkey>,
+    pub fee_recipient: Pubkey,
+    pub platform_fee: u64,
+    pub default_fee_limits: FeeLimits,
+}
+
+impl GlobalConfig {
+    pub fn emit_updated(&self) {
+        emit!(events::GlobalConfigUpdated {
+            super_admin: self.super_admin,
+            pending_super_admin: self.pending_super_admin,
+            fee_recipient: self.fee_recipient,
+            platform_fee: self.platform_fee,
+        });
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct InitGlobalConfigIx {
+    super_admin: Pubkey,
+    fee_recipient: Pubkey,
+    platform_fee: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetPlatformFeeIx {
+    platform_fee: Option<u64>,
+    fee_recipient: Option<Pubkey>,
+    default_fee_limits: Option<FeeLimits>,
+}
+
 #[account]
 pub struct StakePool {
     pub bump: u8,
@@ -560,6 +2534,549 @@ pub struct StakePool {
     pub pool_name: String,
     pub default_multiplier: u64,
     pub created_at: i64,
+    pub min_stake_seconds: u32,
+    pub reward_per_token_stored: u128,
+    pub last_reward_update: i64,
+    pub lock_policy: LockPolicy,
+    pub fee_config: FeeConfig,
+    pub fee_limits: FeeLimits,
+    pub reward_mint: Pubkey,
+    pub reward_vault: Pubkey,
+    /// Sum of `effective_stake * apr` over all entries.
+    pub total_reward_weight: u128,
+    /// Rewards accrued to stakers but not yet paid out or forfeited.
+    pub total_rewards_owed: u64,
+    /// Reward tokens deposited into the vault, net of excess withdrawals.
+    pub total_rewards_funded: u64,
+    pub total_rewards_paid: u64,
+    /// Sum of `reward_iou` over all entries.
+    pub total_reward_ious: u64,
+    /// Set by the super admin to let stakers exit through
+    /// `emergency_withdraw`.
+    pub emergency_withdraw_enabled: bool,
+    pub emergency_withdraw_fee_bps: u16,
+    /// Lock periods stakers can choose from, each with its own APR.
+    pub lock_tiers: Vec<LockTier>,
+    /// Boost applied to a position's stake weight based on its lock.
+    pub boost_curve: BoostCurve,
+    pub reward_mode: RewardMode,
+    /// Number of `RewardStream` accounts created for this pool.
+    pub reward_stream_count: u8,
+    /// Share of compounded rewards paid to whoever runs `crank_compound`.
+    pub compound_tip_bps: u16,
+}
+
+/// Additional reward token paid alongside the pool rewards, shared by
+/// stake weight at a fixed rate until `end_time`.
+#[account]
+pub struct RewardStream {
+    pub bump: u8,
+    pub pool: Pubkey,
+    pub index: u8,
+    pub reward_mint: Pubkey,
+    pub reward_vault: Pubkey,
+    pub rate_per_second: u64,
+    pub end_time: i64,
+    pub reward_per_weight_stored: u128,
+    pub last_update: i64,
+    pub total_funded: u64,
+    pub total_paid: u64,
+    /// May withdraw what the stream does not owe and close it.
+    pub authority: Pubkey,
+    /// Emissions allocated to stakers but not yet paid or forfeited. Rounded
+    /// up, like `StakePool.total_rewards_owed`.
+    pub total_rewards_owed: u64,
+    /// Sum of `stream_pending_rewards` over all entries.
+    pub total_rewards_pending: u64,
+}
+
+impl RewardStream {
+    /// Reads stream `index` of `pool` from an account passed in
+    /// `remaining_accounts`.
+    pub fn load(info: &AccountInfo, pool: Pubkey, index: usize) -> Result<RewardStream> {
+        if *info.owner != crate::ID || !info.is_writable {
+            return err!(errors::ErrorCode::InvalidRewardStream);
+        }
+        let stream = RewardStream::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        if stream.pool != pool || stream.index as usize != index {
+            return err!(errors::ErrorCode::InvalidRewardStream);
+        }
+        Ok(stream)
+    }
+
+    pub fn store(&self, info: &AccountInfo) -> Result<()> {
+        let mut data = info.try_borrow_mut_data()?;
+        self.try_serialize(&mut &mut data[..])
+    }
+
+    /// Advances the accumulator to `now` over the pool's current weight.
+    /// Like fixed emission pools, nothing accrues while nothing is staked.
+    pub fn update(&mut self, now: i64, total_reward_weight: u128) -> Result<()> {
+        let until = now.min(self.end_time);
+        if until > self.last_update && total_reward_weight > 0 {
+            let emitted = math::checked_mul(
+                self.rate_per_second as u128,
+                (until - self.last_update) as u128,
+            )?;
+            let accrued = mul_div(
+                emitted,
+                REWARD_PRECISION * BASIS_POINTS,
+                total_reward_weight,
+                Rounding::Down,
+            )?;
+            self.reward_per_weight_stored = self
+                .reward_per_weight_stored
+                .checked_add(math::checked_mul(accrued, BASIS_POINTS)?)
+                .ok_or(errors::ErrorCode::MathOverflow)?;
+            self.total_rewards_owed = checked_add(self.total_rewards_owed, to_u64(emitted)?)?;
+        }
+        self.last_update = self.last_update.max(now);
+        Ok(())
+    }
+
+    /// Drops `amount` of an entry's pending rewards from the stream's
+    /// obligations once it has been paid or forfeited.
+    pub fn release_rewards(&mut self, amount: u64) {
+        self.total_rewards_owed = self.total_rewards_owed.saturating_sub(amount);
+        self.total_rewards_pending = self.total_rewards_pending.saturating_sub(amount);
+    }
+}
+
+/// `StakePool` layout written by the first release, where
+/// `default_multiplier` doubled as the stake mint's decimals. Only read by
+/// `migrate_pool`.
+#[derive(AnchorDeserialize)]
+pub struct LegacyStakePool {
+    pub bump: u8,
+    pub authority: Pubkey,
+    pub total_staked: u64,
+    pub total_stakers: u32,
+    pub token_address: Pubkey,
+    pub apr: u64,
+    pub end_date: Option<i64>,
+    pub is_active: bool,
+    pub identifier: String,
+    pub pool_name: String,
+    pub default_multiplier: u64,
+    pub created_at: i64,
+}
+
+/// `StakeEntry` layout written by the first release. Only read by
+/// `migrate_stake_entry`.
+#[derive(AnchorDeserialize)]
+pub struct LegacyStakeEntry {
+    pub bump: u8,
+    pub pool: Pubkey,
+    pub amount: u64,
+    pub stake_mint: Pubkey,
+    pub last_staker: Pubkey,
+    pub last_staked_at: i64,
+    pub min_stake_seconds: Option<u32>,
+    pub apr: u64,
+}
+
+/// How the lock timer of an existing position reacts to a top-up.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LockPolicy {
+    /// The whole position is locked again from the time of the top-up.
+    Reset,
+    /// The stake time moves to the amount-weighted average of the old
+    /// position and the top-up.
+    WeightedAverage,
+}
+
+/// A lock period offered by a pool. `apr_bps` scales the pool APR, so
+/// 10_000 pays exactly `StakePool.apr`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct LockTier {
+    pub min_stake_seconds: u32,
+    pub apr_bps: u16,
+}
+
+/// How a pool's rewards are generated.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RewardMode {
+    /// Every position earns `apr` on its stake, scaled by its lock tier.
+    Apr,
+    /// The pool emits `rate_per_second` tokens between `start` and `end`,
+    /// shared pro-rata by stake weight. The rate halves after every
+    /// `halving_period` seconds, if set.
+    FixedEmission {
+        rate_per_second: u64,
+        start: i64,
+        end: i64,
+        halving_period: Option<i64>,
+    },
+}
+
+impl RewardMode {
+    /// Whether `StakePool.apr` drives the rewards.
+    pub fn uses_apr(&self) -> bool {
+        matches!(self, RewardMode::Apr)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let RewardMode::FixedEmission {
+            rate_per_second,
+            start,
+            end,
+            halving_period,
+        } = *self
+        {
+            if rate_per_second == 0 || end <= start || halving_period.is_some_and(|p| p <= 0) {
+                return err!(errors::ErrorCode::InvalidRewardMode);
+            }
+        }
+        Ok(())
+    }
+
+    /// Tokens emitted between `from` and `to`. Always zero in `Apr` mode.
+    pub fn emitted_between(&self, from: i64, to: i64) -> Result<u64> {
+        let RewardMode::FixedEmission {
+            rate_per_second,
+            start,
+            end,
+            halving_period,
+        } = *self
+        else {
+            return Ok(0);
+        };
+
+        let from = from.max(start);
+        let to = to.min(end);
+        let Some(period) = halving_period else {
+            let elapsed = to.saturating_sub(from).max(0) as u64;
+            return to_u64(math::checked_mul(rate_per_second as u128, elapsed as u128)?);
+        };
+
+        let mut emitted: u64 = 0;
+        let mut cursor = from;
+        while cursor < to {
+            let halvings = (cursor - start) / period;
+            // The rate has reached zero.
+            if halvings >= u64::BITS as i64 {
+                break;
+            }
+            let period_end = start.saturating_add(period.saturating_mul(halvings + 1));
+            let segment_end = to.min(period_end);
+            let segment = to_u64(math::checked_mul(
+                (rate_per_second >> halvings) as u128,
+                (segment_end - cursor) as u128,
+            )?)?;
+            emitted = checked_add(emitted, segment)?;
+            cursor = segment_end;
+        }
+        Ok(emitted)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct BoostStep {
+    pub min_lock_seconds: u32,
+    pub multiplier: u64,
+}
+
+/// Maps a position's lock duration to a stake weight multiplier, where
+/// `MULTIPLIER_BASE` is 1x.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum BoostCurve {
+    None,
+    /// Grows from 1x at no lock to `max_multiplier` at `max_lock_seconds`.
+    Linear {
+        max_lock_seconds: u32,
+        max_multiplier: u64,
+    },
+    /// Uses the highest step whose `min_lock_seconds` the lock reaches.
+    /// Steps are sorted by `min_lock_seconds`.
+    Stepwise {
+        steps: Vec<BoostStep>,
+    },
+}
+
+impl BoostCurve {
+    pub fn validate(&self) -> Result<()> {
+        let is_valid = match self {
+            BoostCurve::None => true,
+            BoostCurve::Linear {
+                max_lock_seconds,
+                max_multiplier,
+            } => {
+                *max_lock_seconds > 0
+                    && (MULTIPLIER_BASE..=MAX_BOOST_MULTIPLIER).contains(max_multiplier)
+            }
+            BoostCurve::Stepwise { steps } => {
+                !steps.is_empty()
+                    && steps.len() <= MAX_BOOST_STEPS
+                    && steps.iter().all(|step| {
+                        (MULTIPLIER_BASE..=MAX_BOOST_MULTIPLIER).contains(&step.multiplier)
+                    })
+                    && steps.windows(2).all(|pair| {
+                        pair[0].min_lock_seconds < pair[1].min_lock_seconds
+                            && pair[0].multiplier <= pair[1].multiplier
+                    })
+            }
+        };
+
+        if !is_valid {
+            return err!(errors::ErrorCode::InvalidBoostCurve);
+        }
+        Ok(())
+    }
+
+    pub fn multiplier(&self, lock_seconds: u32) -> Result<u64> {
+        match self {
+            BoostCurve::None => Ok(MULTIPLIER_BASE),
+            BoostCurve::Linear {
+                max_lock_seconds,
+                max_multiplier,
+            } => {
+                let bonus = mul_div_u64(
+                    max_multiplier - MULTIPLIER_BASE,
+                    lock_seconds.min(*max_lock_seconds) as u64,
+                    *max_lock_seconds as u64,
+                    Rounding::Down,
+                )?;
+                checked_add(MULTIPLIER_BASE, bonus)
+            }
+            BoostCurve::Stepwise { steps } => Ok(steps
+                .iter()
+                .rev()
+                .find(|step| lock_seconds >= step.min_lock_seconds)
+                .map_or(MULTIPLIER_BASE, |step| step.multiplier)),
+        }
+    }
+}
+
+impl LockTier {
+    pub fn validate_tiers(tiers: &[LockTier]) -> Result<()> {
+        if tiers.is_empty() || tiers.len() > MAX_LOCK_TIERS {
+            return err!(errors::ErrorCode::InvalidLockTier);
+        }
+        if tiers.iter().any(|tier| tier.apr_bps == 0) {
+            return err!(errors::ErrorCode::InvalidLockTier);
+        }
+        Ok(())
+    }
+}
+
+/// Fees charged by `unstake_token`, in basis points.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct FeeConfig {
+    /// Penalty on the unstaked amount when leaving before the lock expires.
+    pub early_unstake_penalty_bps: u16,
+    /// Part of the early unstake penalty that goes to the super admin.
+    pub penalty_admin_share_bps: u16,
+    /// Fee on the unstaked amount once the lock has expired.
+    pub unstake_fee_bps: u16,
+    /// Super admin cut, relative to the unstake fee, charged on frozen pools.
+    pub frozen_admin_share_bps: u16,
+}
+
+impl FeeConfig {
+    pub const DEFAULT: FeeConfig = FeeConfig {
+        early_unstake_penalty_bps: 3_000,
+        penalty_admin_share_bps: 200,
+        unstake_fee_bps: 100,
+        frozen_admin_share_bps: 60,
+    };
+
+    pub fn validate(&self, limits: &FeeLimits) -> Result<()> {
+        if self.early_unstake_penalty_bps > limits.max_early_unstake_penalty_bps
+            || self.unstake_fee_bps > limits.max_unstake_fee_bps
+            || self.penalty_admin_share_bps < limits.min_penalty_admin_share_bps
+            || self.frozen_admin_share_bps < limits.min_frozen_admin_share_bps
+            || self.penalty_admin_share_bps as u128 > BASIS_POINTS
+            || self.frozen_admin_share_bps as u128 > BASIS_POINTS
+        {
+            return err!(errors::ErrorCode::InvalidFeeConfig);
+        }
+        Ok(())
+    }
+
+    /// Brings the config back within `limits`, changing only the fields
+    /// that fall outside them.
+    pub fn clamp_to(&self, limits: &FeeLimits) -> FeeConfig {
+        FeeConfig {
+            early_unstake_penalty_bps: self
+                .early_unstake_penalty_bps
+                .min(limits.max_early_unstake_penalty_bps),
+            penalty_admin_share_bps: self
+                .penalty_admin_share_bps
+                .max(limits.min_penalty_admin_share_bps),
+            unstake_fee_bps: self.unstake_fee_bps.min(limits.max_unstake_fee_bps),
+            frozen_admin_share_bps: self
+                .frozen_admin_share_bps
+                .max(limits.min_frozen_admin_share_bps),
+        }
+    }
+}
+
+/// Bounds the super admin puts on a pool's `FeeConfig`. Pool authorities may
+/// charge stakers up to the maxima but cannot cut the super admin's share
+/// below the minima.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct FeeLimits {
+    pub max_early_unstake_penalty_bps: u16,
+    pub max_unstake_fee_bps: u16,
+    pub min_penalty_admin_share_bps: u16,
+    pub min_frozen_admin_share_bps: u16,
+}
+
+impl FeeLimits {
+    pub const DEFAULT: FeeLimits = FeeLimits {
+        max_early_unstake_penalty_bps: 5_000,
+        max_unstake_fee_bps: 1_000,
+        min_penalty_admin_share_bps: 200,
+        min_frozen_admin_share_bps: 60,
+    };
+
+    pub fn validate(&self) -> Result<()> {
+        if self.max_early_unstake_penalty_bps as u128 > BASIS_POINTS
+            || self.max_unstake_fee_bps as u128 > BASIS_POINTS
+            || self.min_penalty_admin_share_bps as u128 > BASIS_POINTS
+            || self.min_frozen_admin_share_bps as u128 > BASIS_POINTS
+        {
+            return err!(errors::ErrorCode::InvalidFeeConfig);
+        }
+        Ok(())
+    }
+}
+
+impl StakePool {
+    /// Advances the reward-per-token accumulator to `now`. Rewards stop
+    /// accruing at `end_date`; time past it is never credited, even if the
+    /// end date is later extended.
+    pub fn update_rewards(&mut self, now: i64) -> Result<()> {
+        let accrue_until = match self.end_date {
+            Some(end_date) => now.min(end_date),
+            None => now,
+        };
+
+        if accrue_until > self.last_reward_update {
+            let (accrued, owed) = self.accrue(self.last_reward_update, accrue_until)?;
+            self.reward_per_token_stored = self
+                .reward_per_token_stored
+                .checked_add(accrued)
+                .ok_or(errors::ErrorCode::MathOverflow)?;
+            self.total_rewards_owed = checked_add(self.total_rewards_owed, owed)?;
+        }
+
+        self.last_reward_update = self.last_reward_update.max(now);
+        Ok(())
+    }
+
+    /// Returns the accumulator increase between `from` and `to` together
+    /// with the rewards it allocates to the current stake weight.
+    fn accrue(&self, from: i64, to: i64) -> Result<(u128, u64)> {
+        match self.reward_mode {
+            RewardMode::Apr => {
+                let accrued = self.reward_per_token_between(from, to)?;
+                Ok((accrued, self.rewards_for_weight(accrued)?))
+            }
+            RewardMode::FixedEmission { .. } => {
+                // Emissions while nothing is staked are not allocated.
+                let emitted = self.reward_mode.emitted_between(from, to)?;
+                if emitted == 0 || self.total_reward_weight == 0 {
+                    return Ok((0, 0));
+                }
+                let accrued = mul_div(
+                    emitted as u128,
+                    REWARD_PRECISION * BASIS_POINTS,
+                    self.total_reward_weight,
+                    Rounding::Down,
+                )?;
+                Ok((math::checked_mul(accrued, BASIS_POINTS)?, emitted))
+            }
+        }
+    }
+
+    fn reward_per_token_between(&self, from: i64, to: i64) -> Result<u128> {
+        let elapsed = to.saturating_sub(from).max(0) as u128;
+        let rate = math::checked_mul(self.apr as u128, self.default_multiplier as u128)?;
+        mul_div(
+            math::checked_mul(elapsed, rate)?,
+            REWARD_PRECISION,
+            SECONDS_PER_YEAR * MULTIPLIER_BASE as u128,
+            Rounding::Down,
+        )
+    }
+
+    /// Rewards the whole pool earns over an accumulator increase of
+    /// `reward_per_token`. Rounded up so the pool never under-reports what
+    /// it owes.
+    fn rewards_for_weight(&self, reward_per_token: u128) -> Result<u64> {
+        to_u64(mul_div(
+            self.total_reward_weight.div_ceil(BASIS_POINTS),
+            reward_per_token,
+            REWARD_PRECISION * BASIS_POINTS,
+            Rounding::Up,
+        )?)
+    }
+
+    /// Fails if the funds left in the vault cannot cover what is already
+    /// owed plus, for pools that end, everything the current stake will
+    /// earn until then.
+    pub fn ensure_funded(&self, now: i64) -> Result<()> {
+        let unallocated = self
+            .total_rewards_funded
+            .saturating_sub(self.total_rewards_paid)
+            .checked_sub(self.total_rewards_owed)
+            .ok_or(errors::ErrorCode::InsufficientRewardFunding)?;
+
+        let horizon = match self.reward_mode {
+            RewardMode::Apr => self.end_date,
+            RewardMode::FixedEmission { end, .. } => {
+                Some(self.end_date.map_or(end, |end_date| end_date.min(end)))
+            }
+        };
+        let projected = match horizon {
+            Some(horizon) => self.accrue(now, horizon)?.1,
+            None => 0,
+        };
+
+        if projected > unallocated {
+            return err!(errors::ErrorCode::InsufficientRewardFunding);
+        }
+        Ok(())
+    }
+
+    /// Checks shared by `compound` and `crank_compound`.
+    pub fn ensure_compoundable(&self, now: i64) -> Result<()> {
+        if !self.is_active {
+            return err!(errors::ErrorCode::PoolFrozen);
+        }
+
+        if self.end_date.is_some_and(|end_date| now > end_date) {
+            return err!(errors::ErrorCode::StakePoolHasEnded);
+        }
+
+        if self.reward_mint != self.token_address {
+            return err!(errors::ErrorCode::CompoundNotSupported);
+        }
+        Ok(())
+    }
+
+    pub fn add_reward_weight(&mut self, weight: u128) -> Result<()> {
+        self.total_reward_weight = self
+            .total_reward_weight
+            .checked_add(weight)
+            .ok_or(errors::ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn remove_reward_weight(&mut self, weight: u128) -> Result<()> {
+        self.total_reward_weight = self
+            .total_reward_weight
+            .checked_sub(weight)
+            .ok_or(errors::ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Drops `amount` from the outstanding obligations once it has been
+    /// paid or forfeited. Saturates, as the pool-wide total is rounded up.
+    pub fn release_rewards(&mut self, amount: u64) {
+        self.total_rewards_owed = self.total_rewards_owed.saturating_sub(amount);
+    }
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -573,6 +3090,11 @@ pub struct InitPoolIx {
     pool_name: String,
     default_multiplier: u64,
     amount: u64,
+    lock_policy: LockPolicy,
+    fee_config: Option<FeeConfig>,
+    lock_tiers: Vec<LockTier>,
+    boost_curve: BoostCurve,
+    reward_mode: RewardMode,
 }
 
 #[account]
@@ -581,17 +3103,318 @@ pub struct StakeEntry {
     pub pool: Pubkey,
     pub amount: u64,
     pub stake_mint: Pubkey,
-    pub last_staker: Pubkey,
+    pub owner: Pubkey,
     pub last_staked_at: i64,
     pub min_stake_seconds: Option<u32>,
     pub apr: u64,
+    pub reward_per_token_paid: u128,
+    pub pending_rewards: u64,
+    /// Optional account allowed to claim on the owner's behalf.
+    pub delegate: Option<Pubkey>,
+    /// Rewards earned but not paid because the vault ran short.
+    pub reward_iou: u64,
+    /// Lock tier of the current position.
+    pub tier: u8,
+    /// `amount` scaled by the pool's boost for this position's lock while
+    /// the lock runs; what rewards are actually paid on.
+    pub effective_stake: u64,
+    /// Per-stream checkpoints, indexed like `RewardStream.index`.
+    pub stream_rewards_paid: [u128; MAX_REWARD_STREAMS],
+    pub stream_pending_rewards: [u64; MAX_REWARD_STREAMS],
+    /// Lets anyone compound this entry through `crank_compound`.
+    pub auto_compound: bool,
+}
+
+impl StakeEntry {
+    /// Moves everything earned since the last checkpoint into
+    /// `pending_rewards`. Must run before `effective_stake` or `apr` change.
+    pub fn settle_rewards(&mut self, stake_pool: &StakePool) -> Result<()> {
+        let delta = stake_pool
+            .reward_per_token_stored
+            .checked_sub(self.reward_per_token_paid)
+            .ok_or(errors::ErrorCode::MathOverflow)?;
+        let earned = self.earned(delta)?;
+
+        self.pending_rewards = checked_add(self.pending_rewards, earned)?;
+        self.reward_per_token_paid = stake_pool.reward_per_token_stored;
+        Ok(())
+    }
+
+    /// Same as `settle_rewards`, for reward stream `index`. Positions
+    /// without weight earn nothing and only move their checkpoint, which may
+    /// still point into a closed stream that used the same index.
+    pub fn settle_stream(&mut self, index: usize, stream: &mut RewardStream) -> Result<()> {
+        if self.effective_stake > 0 {
+            let delta = stream
+                .reward_per_weight_stored
+                .checked_sub(self.stream_rewards_paid[index])
+                .ok_or(errors::ErrorCode::MathOverflow)?;
+            let earned = self.earned(delta)?;
+
+            self.stream_pending_rewards[index] =
+                checked_add(self.stream_pending_rewards[index], earned)?;
+            stream.total_rewards_pending = checked_add(stream.total_rewards_pending, earned)?;
+        }
+        self.stream_rewards_paid[index] = stream.reward_per_weight_stored;
+        Ok(())
+    }
+
+    /// Checkpoints every reward stream of the pool, which must be passed in
+    /// `remaining_accounts` in index order. Must run before the entry's
+    /// weight changes.
+    pub fn settle_reward_streams(
+        &mut self,
+        stake_pool: &StakePool,
+        accounts: &[AccountInfo],
+        now: i64,
+    ) -> Result<()> {
+        let stream_accounts = accounts
+            .get(..stake_pool.reward_stream_count as usize)
+            .ok_or(errors::ErrorCode::InvalidRewardStream)?;
+
+        for (index, info) in stream_accounts.iter().enumerate() {
+            let mut stream = RewardStream::load(info, self.pool, index)?;
+            stream.update(now, stake_pool.total_reward_weight)?;
+            self.settle_stream(index, &mut stream)?;
+            stream.store(info)?;
+        }
+
+        // Checkpoints past the pool's streams belong to closed streams. A
+        // stream added at one of those indices starts from zero.
+        for paid in &mut self.stream_rewards_paid[stream_accounts.len()..] {
+            *paid = 0;
+        }
+        Ok(())
+    }
+
+    /// Forfeits the entry's settled stream rewards without advancing any
+    /// stream. Streams are looked up by index in `accounts`; a missing or
+    /// unreadable one keeps the forfeited amount reserved instead of
+    /// failing the call.
+    pub fn forfeit_reward_streams(
+        &mut self,
+        stake_pool: &StakePool,
+        accounts: &[AccountInfo],
+    ) -> Result<()> {
+        for index in 0..stake_pool.reward_stream_count as usize {
+            let pending = self.stream_pending_rewards[index];
+            if pending == 0 {
+                continue;
+            }
+            let Some(info) = accounts.get(index) else {
+                continue;
+            };
+            if let Ok(mut stream) = RewardStream::load(info, self.pool, index) {
+                stream.release_rewards(pending);
+                stream.store(info)?;
+            }
+        }
+        self.stream_pending_rewards = [0; MAX_REWARD_STREAMS];
+        self.stream_rewards_paid = [0; MAX_REWARD_STREAMS];
+        Ok(())
+    }
+
+    /// Rewards earned by the current weight over an accumulator increase of
+    /// `delta`.
+    fn earned(&self, delta: u128) -> Result<u64> {
+        let delta = mul_div(delta, self.apr as u128, BASIS_POINTS, Rounding::Down)?;
+        to_u64(mul_div(
+            self.effective_stake as u128,
+            delta,
+            REWARD_PRECISION * BASIS_POINTS,
+            Rounding::Down,
+        )?)
+    }
+
+    /// Pays `amount` plus any earlier IOU, up to what the vault holds, and
+    /// records the rest as the new IOU. Returns the amount to transfer.
+    pub fn pay_rewards(
+        &mut self,
+        stake_pool: &mut StakePool,
+        amount: u64,
+        available: u64,
+    ) -> Result<u64> {
+        let due = checked_add(self.reward_iou, amount)?;
+        let paid = due.min(available);
+        let iou = due - paid;
+
+        stake_pool.total_reward_ious = checked_add(
+            checked_sub(stake_pool.total_reward_ious, self.reward_iou)?,
+            iou,
+        )?;
+        stake_pool.total_rewards_paid = checked_add(stake_pool.total_rewards_paid, paid)?;
+        stake_pool.release_rewards(paid);
+        self.reward_iou = iou;
+        Ok(paid)
+    }
+
+    /// Whether the position is still inside its minimum stake period.
+    pub fn is_locked(&self, now: i64) -> bool {
+        matches!(self.min_stake_seconds, Some(seconds)
+            if now.saturating_sub(self.last_staked_at) < seconds as i64)
+    }
+
+    /// Recomputes `effective_stake` after `amount` or the lock changed. The
+    /// boost only applies while the lock runs. Rewards must be settled
+    /// first.
+    pub fn refresh_effective_stake(&mut self, stake_pool: &StakePool, now: i64) -> Result<()> {
+        let multiplier = if self.is_locked(now) {
+            stake_pool
+                .boost_curve
+                .multiplier(self.min_stake_seconds.unwrap_or(0))?
+        } else {
+            MULTIPLIER_BASE
+        };
+        self.effective_stake =
+            mul_div_u64(self.amount, multiplier, MULTIPLIER_BASE, Rounding::Down)?;
+        Ok(())
+    }
+
+    /// Drops the boost of a position whose lock has expired. Rewards and
+    /// reward streams must be settled first.
+    pub fn refresh_boost(&mut self, stake_pool: &mut StakePool, now: i64) -> Result<()> {
+        stake_pool.remove_reward_weight(self.reward_weight())?;
+        self.refresh_effective_stake(stake_pool, now)?;
+        stake_pool.add_reward_weight(self.reward_weight())
+    }
+
+    /// Whether the position still carries a boost its lock no longer
+    /// earns.
+    pub fn has_expired_boost(&self, now: i64) -> bool {
+        !self.is_locked(now) && self.effective_stake != self.amount
+    }
+
+    /// Settles the position and its reward streams under the boost it held
+    /// so far, then drops the boost.
+    pub fn drop_expired_boost(
+        &mut self,
+        stake_pool: &mut StakePool,
+        streams: &[AccountInfo],
+        now: i64,
+    ) -> Result<()> {
+        stake_pool.update_rewards(now)?;
+        self.settle_rewards(stake_pool)?;
+        self.settle_reward_streams(stake_pool, streams, now)?;
+        self.refresh_boost(stake_pool, now)
+    }
+
+    pub fn reward_weight(&self) -> u128 {
+        self.effective_stake as u128 * self.apr as u128
+    }
+}
+
+/// Accounts `compound_position` moves tokens between.
+pub struct CompoundAccounts<'info> {
+    pub stake_pool: AccountInfo<'info>,
+    pub reward_vault: AccountInfo<'info>,
+    pub entry_token_account: AccountInfo<'info>,
+    pub stake_mint: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+    /// Receives the tip, if any.
+    pub tip_destination: Option<AccountInfo<'info>>,
+}
+
+/// Settles the entry and moves its pending rewards from the reward vault
+/// into its stake, leaving the lock untouched. With a `tip_destination`,
+/// `tip_bps` of the rewards goes there instead. Returns the amount added to
+/// the stake, zero if nothing could be paid, and the tip.
+pub fn compound_position<'info>(
+    stake_pool: &mut StakePool,
+    stake_entry: &mut StakeEntry,
+    accounts: &CompoundAccounts<'info>,
+    streams: &[AccountInfo<'info>],
+    decimals: u8,
+    tip_bps: u16,
+    now: i64,
+) -> Result<(u64, u64)> {
+    stake_pool.update_rewards(now)?;
+    stake_entry.settle_rewards(stake_pool)?;
+    stake_entry.settle_reward_streams(stake_pool, streams, now)?;
+
+    let pending_rewards = stake_entry.pending_rewards;
+    stake_entry.pending_rewards = 0;
+    let available = token_amount(&accounts.reward_vault)?;
+    let paid = stake_entry.pay_rewards(stake_pool, pending_rewards, available)?;
+    if paid == 0 {
+        return Ok((0, 0));
+    }
+    let tip = match accounts.tip_destination {
+        Some(_) => apply_bps(paid, tip_bps, Rounding::Down)?,
+        None => 0,
+    };
+
+    let pool_seeds = &[
+        STAKE_POOL_PREFIX.as_bytes(),
+        stake_pool.identifier.as_ref(),
+        &[stake_pool.bump],
+    ];
+    let pool_signer_seeds = &[&pool_seeds[..]];
+
+    let transfer_accounts = TransferChecked {
+        from: accounts.reward_vault.clone(),
+        to: accounts.entry_token_account.clone(),
+        authority: accounts.stake_pool.clone(),
+        mint: accounts.stake_mint.clone(),
+    };
+    let transfer_ctx = CpiContext::new_with_signer(
+        accounts.token_program.clone(),
+        transfer_accounts,
+        pool_signer_seeds,
+    );
+
+    let balance_before = token_amount(&accounts.entry_token_account)?;
+    transfer_checked(transfer_ctx, checked_sub(paid, tip)?, decimals)?;
+    let received = checked_sub(token_amount(&accounts.entry_token_account)?, balance_before)?;
+
+    // Paid right away, so the next entry of a batch sees the vault balance
+    // without it.
+    if let Some(tip_destination) = accounts.tip_destination.as_ref().filter(|_| tip > 0) {
+        let tip_accounts = TransferChecked {
+            from: accounts.reward_vault.clone(),
+            to: tip_destination.clone(),
+            authority: accounts.stake_pool.clone(),
+            mint: accounts.stake_mint.clone(),
+        };
+        let tip_ctx = CpiContext::new_with_signer(
+            accounts.token_program.clone(),
+            tip_accounts,
+            pool_signer_seeds,
+        );
+        transfer_checked(tip_ctx, tip, decimals)?;
+    }
+
+    stake_pool.remove_reward_weight(stake_entry.reward_weight())?;
+    stake_entry.amount = checked_add(stake_entry.amount, received)?;
+    stake_entry.refresh_effective_stake(stake_pool, now)?;
+    stake_pool.add_reward_weight(stake_entry.reward_weight())?;
+    stake_pool.total_staked = checked_add(stake_pool.total_staked, received)?;
+    stake_pool.ensure_funded(now)?;
+    Ok((received, tip))
+}
+
+fn token_amount(info: &AccountInfo) -> Result<u64> {
+    Ok(TokenAccount::try_deserialize(&mut &info.try_borrow_data()?[..])?.amount)
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct InitStakeIx {
     amount: u64,
-    min_stake_seconds: Option<u32>,
-    apr: u64,
+    /// Index into `StakePool.lock_tiers`.
+    tier: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetEmergencyWithdrawIx {
+    enabled: bool,
+    fee_bps: u16,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct AddRewardStreamIx {
+    rate_per_second: u64,
+    end_time: i64,
+    /// Initial funding, transferred from the payer.
+    amount: u64,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -601,5 +3424,14 @@ pub struct UnstakeIx {
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct UpdatePoolIx {
+    apr: Option<u64>,
+    end_date: Option<i64>,
+    pool_name: Option<String>,
+    default_multiplier: Option<u64>,
     min_stake_seconds: Option<u32>,
+    lock_policy: Option<LockPolicy>,
+    fee_config: Option<FeeConfig>,
+    lock_tiers: Option<Vec<LockTier>>,
+    boost_curve: Option<BoostCurve>,
+    compound_tip_bps: Option<u16>,
 }
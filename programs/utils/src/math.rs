@@ -0,0 +1,153 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::ErrorCode;
+use crate::BASIS_POINTS;
+
+/// Direction to round the result of a division in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+pub fn checked_add(a: u64, b: u64) -> Result<u64> {
+    a.checked_add(b)
+        .ok_or_else(|| error!(ErrorCode::MathOverflow))
+}
+
+pub fn checked_sub(a: u64, b: u64) -> Result<u64> {
+    a.checked_sub(b)
+        .ok_or_else(|| error!(ErrorCode::MathOverflow))
+}
+
+pub fn checked_mul(a: u128, b: u128) -> Result<u128> {
+    a.checked_mul(b)
+        .ok_or_else(|| error!(ErrorCode::MathOverflow))
+}
+
+pub fn to_u64(value: u128) -> Result<u64> {
+    u64::try_from(value).map_err(|_| error!(ErrorCode::MathOverflow))
+}
+
+/// Computes `value * numerator / denominator` with a `u128` intermediate.
+pub fn mul_div(
+    value: u128,
+    numerator: u128,
+    denominator: u128,
+    rounding: Rounding,
+) -> Result<u128> {
+    if denominator == 0 {
+        return err!(ErrorCode::MathOverflow);
+    }
+
+    let product = checked_mul(value, numerator)?;
+    let quotient = product / denominator;
+
+    if rounding == Rounding::Up && product % denominator != 0 {
+        return quotient
+            .checked_add(1)
+            .ok_or_else(|| error!(ErrorCode::MathOverflow));
+    }
+    Ok(quotient)
+}
+
+/// `mul_div` for `u64` operands whose result must fit back into a `u64`.
+pub fn mul_div_u64(
+    value: u64,
+    numerator: u64,
+    denominator: u64,
+    rounding: Rounding,
+) -> Result<u64> {
+    to_u64(mul_div(
+        value as u128,
+        numerator as u128,
+        denominator as u128,
+        rounding,
+    )?)
+}
+
+/// Returns `bps` basis points of `amount`.
+pub fn apply_bps(amount: u64, bps: u16, rounding: Rounding) -> Result<u64> {
+    to_u64(mul_div(
+        amount as u128,
+        bps as u128,
+        BASIS_POINTS,
+        rounding,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overflow() -> Error {
+        error!(ErrorCode::MathOverflow)
+    }
+
+    #[test]
+    fn checked_helpers_report_overflow() {
+        assert_eq!(checked_add(u64::MAX - 1, 1).unwrap(), u64::MAX);
+        assert_eq!(checked_add(u64::MAX, 1).unwrap_err(), overflow());
+        assert_eq!(checked_sub(5, 5).unwrap(), 0);
+        assert_eq!(checked_sub(0, 1).unwrap_err(), overflow());
+        assert_eq!(checked_mul(u128::MAX, 2).unwrap_err(), overflow());
+    }
+
+    #[test]
+    fn to_u64_rejects_values_above_u64_max() {
+        assert_eq!(to_u64(u64::MAX as u128).unwrap(), u64::MAX);
+        assert_eq!(to_u64(u64::MAX as u128 + 1).unwrap_err(), overflow());
+    }
+
+    #[test]
+    fn mul_div_rounds_in_the_requested_direction() {
+        assert_eq!(mul_div(10, 1, 3, Rounding::Down).unwrap(), 3);
+        assert_eq!(mul_div(10, 1, 3, Rounding::Up).unwrap(), 4);
+        // Exact results are never bumped up.
+        assert_eq!(mul_div(9, 1, 3, Rounding::Up).unwrap(), 3);
+        assert_eq!(mul_div(0, 7, 3, Rounding::Up).unwrap(), 0);
+    }
+
+    #[test]
+    fn mul_div_keeps_a_wide_intermediate() {
+        let value = u64::MAX as u128;
+        assert_eq!(mul_div(value, value, value, Rounding::Down).unwrap(), value);
+    }
+
+    #[test]
+    fn mul_div_rejects_a_zero_denominator() {
+        assert_eq!(mul_div(1, 1, 0, Rounding::Down).unwrap_err(), overflow());
+        assert_eq!(mul_div_u64(1, 1, 0, Rounding::Up).unwrap_err(), overflow());
+    }
+
+    #[test]
+    fn mul_div_reports_overflow() {
+        assert_eq!(
+            mul_div(u128::MAX, 2, 1, Rounding::Down).unwrap_err(),
+            overflow()
+        );
+        assert_eq!(mul_div(u128::MAX, 1, u128::MAX, Rounding::Up).unwrap(), 1);
+    }
+
+    #[test]
+    fn mul_div_u64_rejects_results_above_u64_max() {
+        assert_eq!(
+            mul_div_u64(u64::MAX, 2, 1, Rounding::Down).unwrap_err(),
+            overflow()
+        );
+        assert_eq!(
+            mul_div_u64(u64::MAX, 2, 2, Rounding::Down).unwrap(),
+            u64::MAX
+        );
+    }
+
+    #[test]
+    fn apply_bps_rounds_in_the_requested_direction() {
+        assert_eq!(apply_bps(10_000, 250, Rounding::Down).unwrap(), 250);
+        assert_eq!(apply_bps(1, 1, Rounding::Down).unwrap(), 0);
+        assert_eq!(apply_bps(1, 1, Rounding::Up).unwrap(), 1);
+        assert_eq!(apply_bps(999, 3_000, Rounding::Down).unwrap(), 299);
+        assert_eq!(apply_bps(999, 3_000, Rounding::Up).unwrap(), 300);
+        assert_eq!(apply_bps(u64::MAX, 10_000, Rounding::Up).unwrap(), u64::MAX);
+    }
+}
@@ -2,8 +2,12 @@ use std::cmp::Ordering;
 
 use anchor_lang::prelude::*;
 use anchor_lang::Result;
+use anchor_spl::token_2022::spl_token_2022;
 use solana_program::program::invoke;
 use solana_program::system_instruction::transfer;
+use spl_token_2022::extension::transfer_fee::instruction::harvest_withheld_tokens_to_mint;
+use spl_token_2022::extension::transfer_fee::TransferFeeAmount;
+use spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensions};
 
 pub fn resize_account<'info>(
     account_info: &AccountInfo<'info>,
@@ -30,10 +34,40 @@ pub fn resize_account<'info>(
             **payer.try_borrow_mut_lamports()? = payer
                 .lamports()
                 .checked_add(lamports_diff)
-                .expect("Add error");
+                .ok_or(crate::errors::ErrorCode::MathOverflow)?;
         }
         Ordering::Equal => {}
     }
     account_info.realloc(new_space, false)?;
     Ok(())
 }
+
+/// Moves transfer fees withheld in a Token-2022 `account` to its mint, as an
+/// account still holding withheld fees cannot be closed. Does nothing for
+/// the original token program or mints without a transfer fee.
+pub fn harvest_withheld_fees<'info>(
+    token_program: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+) -> Result<()> {
+    if token_program.key() != spl_token_2022::ID {
+        return Ok(());
+    }
+
+    let withheld = {
+        let data = account.try_borrow_data()?;
+        let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+        state
+            .get_extension::<TransferFeeAmount>()
+            .map_or(0, |fee| u64::from(fee.withheld_amount))
+    };
+    if withheld == 0 {
+        return Ok(());
+    }
+
+    invoke(
+        &harvest_withheld_tokens_to_mint(token_program.key, mint.key, &[account.key])?,
+        &[mint.clone(), account.clone(), token_program.clone()],
+    )?;
+    Ok(())
+}
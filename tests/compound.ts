@@ -0,0 +1,406 @@
+import * as anchor from "@coral-xyz/anchor";
+import BN from "bn.js";
+import assert from "assert";
+import * as web3 from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import type { Utils } from "../target/types/utils";
+
+const BPF_LOADER_UPGRADEABLE_PROGRAM_ID = new web3.PublicKey(
+  "BPFLoaderUpgradeab1e11111111111111111111111"
+);
+
+const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+
+async function expectError(promise: Promise<unknown>, code: string) {
+  let error: unknown;
+  try {
+    await promise;
+  } catch (e) {
+    error = e;
+  }
+  assert.ok(
+    error instanceof anchor.AnchorError,
+    `expected ${code}, got ${error}`
+  );
+  assert.strictEqual(error.error.errorCode.code, code);
+}
+
+describe("compound", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Utils as anchor.Program<Utils>;
+  const wallet = provider.wallet as anchor.Wallet;
+  const payer = wallet.payer;
+
+  const identifier = `compound-test-${Date.now()}`;
+  const stakeAmount = new BN(1_000_000_000);
+
+  const [globalConfig] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("global-config")],
+    program.programId
+  );
+  const poolAddress = (id: string) =>
+    web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("stake-pool"), Buffer.from(id)],
+      program.programId
+    )[0];
+  const stakePool = poolAddress(identifier);
+
+  let stakeMint: web3.PublicKey;
+  let payerStakeAccount: web3.PublicKey;
+  let rewardVault: web3.PublicKey;
+  let stakeEntry: web3.PublicKey;
+  let entryTokenAccount: web3.PublicKey;
+
+  const balance = async (account: web3.PublicKey) =>
+    new BN(
+      (await getAccount(provider.connection, account)).amount.toString()
+    );
+
+  const entryAddress = (pool: web3.PublicKey, owner: web3.PublicKey) =>
+    web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("stake-entry"),
+        pool.toBuffer(),
+        stakeMint.toBuffer(),
+        owner.toBuffer(),
+      ],
+      program.programId
+    )[0];
+
+  const initPool = async (id: string) => {
+    const pool = poolAddress(id);
+    const config = await program.account.globalConfig.fetch(globalConfig);
+    await program.methods
+      .initPool({
+        authority: payer.publicKey,
+        tokenAddress: stakeMint,
+        apr: new BN(100_000_000),
+        isActive: true,
+        endDate: null,
+        identifier: id,
+        poolName: "Compound test pool",
+        defaultMultiplier: new BN(10_000),
+        amount: new BN(1e12),
+        lockPolicy: { reset: {} },
+        feeConfig: null,
+        lockTiers: [{ minStakeSeconds: 0, aprBps: 10_000 }],
+        boostCurve: { none: {} },
+        rewardMode: { apr: {} },
+      })
+      .accounts({
+        stakePool: pool,
+        rewardVault: getAssociatedTokenAddressSync(stakeMint, pool, true),
+        mint: stakeMint,
+        rewardMint: stakeMint,
+        globalConfig,
+        feeRecipient: config.feeRecipient,
+        payerRewardTokenAccount: payerStakeAccount,
+        payer: payer.publicKey,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+  };
+
+  const initEntry = async (pool: web3.PublicKey, owner = payer) => {
+    const entry = entryAddress(pool, owner.publicKey);
+    await program.methods
+      .initStakeEntry()
+      .accounts({
+        stakeEntry: entry,
+        stakePool: pool,
+        stakeMint,
+        entryTokenAccount: getAssociatedTokenAddressSync(
+          stakeMint,
+          entry,
+          true
+        ),
+        payer: owner.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([owner])
+      .rpc();
+    return entry;
+  };
+
+  before(async () => {
+    const connection = provider.connection;
+    if ((await connection.getAccountInfo(globalConfig)) === null) {
+      const [programData] = web3.PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        BPF_LOADER_UPGRADEABLE_PROGRAM_ID
+      );
+      await program.methods
+        .initGlobalConfig({
+          superAdmin: payer.publicKey,
+          feeRecipient: payer.publicKey,
+          platformFee: new BN(0),
+        })
+        .accounts({
+          globalConfig,
+          program: program.programId,
+          programData,
+          payer: payer.publicKey,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+    }
+
+    stakeMint = await createMint(connection, payer, payer.publicKey, null, 6);
+    payerStakeAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        connection,
+        payer,
+        stakeMint,
+        payer.publicKey
+      )
+    ).address;
+    await mintTo(connection, payer, stakeMint, payerStakeAccount, payer, 1e13);
+
+    await initPool(identifier);
+    rewardVault = getAssociatedTokenAddressSync(stakeMint, stakePool, true);
+
+    stakeEntry = await initEntry(stakePool);
+    entryTokenAccount = getAssociatedTokenAddressSync(
+      stakeMint,
+      stakeEntry,
+      true
+    );
+    await program.methods
+      .stakeToken({ amount: stakeAmount, tier: 0 })
+      .accounts({
+        stakeEntry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        payerTokenAccount: payerStakeAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+        payer: payer.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+  });
+
+  it("adds rewards to the stake without resetting the lock", async () => {
+    const entryBefore = await program.account.stakeEntry.fetch(stakeEntry);
+    const poolBefore = await program.account.stakePool.fetch(stakePool);
+    await sleep(2_000);
+
+    await program.methods
+      .compound()
+      .accounts({
+        stakeEntry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        rewardVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        payer: payer.publicKey,
+      })
+      .rpc();
+
+    const entry = await program.account.stakeEntry.fetch(stakeEntry);
+    const pool = await program.account.stakePool.fetch(stakePool);
+    const compounded = entry.amount.sub(entryBefore.amount);
+    assert.ok(compounded.gtn(0));
+    assert.strictEqual(
+      pool.totalStaked.sub(poolBefore.totalStaked).toString(),
+      compounded.toString()
+    );
+    assert.strictEqual(
+      entry.lastStakedAt.toString(),
+      entryBefore.lastStakedAt.toString()
+    );
+    assert.strictEqual(
+      (await balance(entryTokenAccount)).toString(),
+      entry.amount.toString()
+    );
+  });
+
+  describe("crank_compound", () => {
+    const tipBps = 500;
+    const staker = web3.Keypair.generate();
+    let stakerEntry: web3.PublicKey;
+    let stakerEntryTokenAccount: web3.PublicKey;
+
+    const crank = (entries: [web3.PublicKey, web3.PublicKey][]) =>
+      program.methods
+        .crankCompound()
+        .accounts({
+          stakePool,
+          stakeMint,
+          rewardVault,
+          payerTokenAccount: payerStakeAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          payer: payer.publicKey,
+        })
+        .remainingAccounts(
+          entries.flatMap(([entry, tokenAccount]) => [
+            { pubkey: entry, isSigner: false, isWritable: true },
+            { pubkey: tokenAccount, isSigner: false, isWritable: true },
+          ])
+        )
+        .rpc();
+
+    before(async () => {
+      const connection = provider.connection;
+      await provider.sendAndConfirm(
+        new web3.Transaction().add(
+          web3.SystemProgram.transfer({
+            fromPubkey: payer.publicKey,
+            toPubkey: staker.publicKey,
+            lamports: web3.LAMPORTS_PER_SOL / 10,
+          })
+        )
+      );
+      const stakerStakeAccount = (
+        await getOrCreateAssociatedTokenAccount(
+          connection,
+          payer,
+          stakeMint,
+          staker.publicKey
+        )
+      ).address;
+      await mintTo(
+        connection,
+        payer,
+        stakeMint,
+        stakerStakeAccount,
+        payer,
+        stakeAmount.toNumber()
+      );
+
+      await program.methods
+        .updatePool({
+          apr: null,
+          endDate: null,
+          poolName: null,
+          defaultMultiplier: null,
+          minStakeSeconds: null,
+          lockPolicy: null,
+          feeConfig: null,
+          lockTiers: null,
+          boostCurve: null,
+          compoundTipBps: tipBps,
+        })
+        .accounts({
+          stakePool,
+          payer: payer.publicKey,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+
+      stakerEntry = await initEntry(stakePool, staker);
+      stakerEntryTokenAccount = getAssociatedTokenAddressSync(
+        stakeMint,
+        stakerEntry,
+        true
+      );
+      await program.methods
+        .stakeToken({ amount: stakeAmount, tier: 0 })
+        .accounts({
+          stakeEntry: stakerEntry,
+          stakePool,
+          stakeMint,
+          entryTokenAccount: stakerEntryTokenAccount,
+          payerTokenAccount: stakerStakeAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          rent: web3.SYSVAR_RENT_PUBKEY,
+          payer: staker.publicKey,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .signers([staker])
+        .rpc();
+    });
+
+    it("skips entries that did not opt in", async () => {
+      const tipBefore = await balance(payerStakeAccount);
+      const before = await program.account.stakeEntry.fetch(stakerEntry);
+      await sleep(2_000);
+
+      await crank([
+        [stakeEntry, entryTokenAccount],
+        [stakerEntry, stakerEntryTokenAccount],
+      ]);
+
+      const after = await program.account.stakeEntry.fetch(stakerEntry);
+      assert.strictEqual(after.amount.toString(), before.amount.toString());
+      assert.strictEqual(
+        (await balance(payerStakeAccount)).toString(),
+        tipBefore.toString()
+      );
+    });
+
+    it("compounds opted-in entries and pays the tip", async () => {
+      await program.methods
+        .setAutoCompound(true)
+        .accounts({ stakeEntry: stakerEntry, payer: staker.publicKey })
+        .signers([staker])
+        .rpc();
+
+      const tipBefore = await balance(payerStakeAccount);
+      const ownBefore = await program.account.stakeEntry.fetch(stakeEntry);
+      const before = await program.account.stakeEntry.fetch(stakerEntry);
+      await sleep(2_000);
+
+      await crank([
+        [stakeEntry, entryTokenAccount],
+        [stakerEntry, stakerEntryTokenAccount],
+      ]);
+
+      const after = await program.account.stakeEntry.fetch(stakerEntry);
+      const compounded = after.amount.sub(before.amount);
+      const tip = (await balance(payerStakeAccount)).sub(tipBefore);
+      assert.ok(compounded.gtn(0));
+      assert.ok(tip.gtn(0));
+      assert.strictEqual(
+        tip.toString(),
+        compounded.add(tip).muln(tipBps).divn(10_000).toString()
+      );
+
+      const own = await program.account.stakeEntry.fetch(stakeEntry);
+      assert.strictEqual(own.amount.toString(), ownBefore.amount.toString());
+    });
+
+    it("rejects an entry from another pool", async () => {
+      const otherIdentifier = `compound-other-${Date.now()}`;
+      await initPool(otherIdentifier);
+      const otherEntry = await initEntry(poolAddress(otherIdentifier));
+
+      await expectError(
+        crank([
+          [
+            otherEntry,
+            getAssociatedTokenAddressSync(stakeMint, otherEntry, true),
+          ],
+        ]),
+        "InvalidStakeEntry"
+      );
+    });
+
+    it("rejects a token account that is not the entry's ATA", async () => {
+      await expectError(
+        crank([[stakerEntry, entryTokenAccount]]),
+        "InvalidStakeEntry"
+      );
+    });
+  });
+});
@@ -0,0 +1,296 @@
+import * as anchor from "@coral-xyz/anchor";
+import BN from "bn.js";
+import assert from "assert";
+import * as web3 from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import type { Utils } from "../target/types/utils";
+
+const BPF_LOADER_UPGRADEABLE_PROGRAM_ID = new web3.PublicKey(
+  "BPFLoaderUpgradeab1e11111111111111111111111"
+);
+
+const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+
+async function expectError(promise: Promise<unknown>, code: string) {
+  let error: unknown;
+  try {
+    await promise;
+  } catch (e) {
+    error = e;
+  }
+  assert.ok(
+    error instanceof anchor.AnchorError,
+    `expected ${code}, got ${error}`
+  );
+  assert.strictEqual(error.error.errorCode.code, code);
+}
+
+describe("emergency withdraw", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Utils as anchor.Program<Utils>;
+  const wallet = provider.wallet as anchor.Wallet;
+  const payer = wallet.payer;
+  const staker = web3.Keypair.generate();
+
+  const identifier = `emergency-${Date.now()}`;
+  const stakeAmount = new BN(1_000_000_000);
+  const topUp = new BN(1_000_000);
+  const feeBps = 500;
+
+  const [globalConfig] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("global-config")],
+    program.programId
+  );
+  const [stakePool] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("stake-pool"), Buffer.from(identifier)],
+    program.programId
+  );
+
+  let stakeMint: web3.PublicKey;
+  let rewardMint: web3.PublicKey;
+  let payerStakeAccount: web3.PublicKey;
+  let payerRewardAccount: web3.PublicKey;
+  let stakerStakeAccount: web3.PublicKey;
+  let stakerRewardAccount: web3.PublicKey;
+  let stakeEntry: web3.PublicKey;
+  let entryTokenAccount: web3.PublicKey;
+
+  const balance = async (account: web3.PublicKey) =>
+    new BN(
+      (await getAccount(provider.connection, account)).amount.toString()
+    );
+
+  const tokenAccount = async (mint: web3.PublicKey, owner: web3.PublicKey) =>
+    (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payer,
+        mint,
+        owner
+      )
+    ).address;
+
+  const stake = (amount: BN) =>
+    program.methods
+      .stakeToken({ amount, tier: 0 })
+      .accounts({
+        stakeEntry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        payerTokenAccount: stakerStakeAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+        payer: staker.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([staker])
+      .rpc();
+
+  const emergencyWithdraw = () =>
+    program.methods
+      .emergencyWithdraw()
+      .accounts({
+        stakeEntry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        payerTokenAccount: stakerStakeAccount,
+        poolOwnerTokenAccount: payerStakeAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        payer: staker.publicKey,
+      })
+      .signers([staker])
+      .rpc();
+
+  before(async function () {
+    const connection = provider.connection;
+    if ((await connection.getAccountInfo(globalConfig)) === null) {
+      const [programData] = web3.PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        BPF_LOADER_UPGRADEABLE_PROGRAM_ID
+      );
+      await program.methods
+        .initGlobalConfig({
+          superAdmin: payer.publicKey,
+          feeRecipient: payer.publicKey,
+          platformFee: new BN(0),
+        })
+        .accounts({
+          globalConfig,
+          program: program.programId,
+          programData,
+          payer: payer.publicKey,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+    }
+    const config = await program.account.globalConfig.fetch(globalConfig);
+    // Only the super admin can enable emergency withdrawals.
+    if (!config.superAdmin.equals(payer.publicKey)) {
+      this.skip();
+    }
+
+    await provider.sendAndConfirm(
+      new web3.Transaction().add(
+        web3.SystemProgram.transfer({
+          fromPubkey: payer.publicKey,
+          toPubkey: staker.publicKey,
+          lamports: web3.LAMPORTS_PER_SOL / 10,
+        })
+      )
+    );
+
+    stakeMint = await createMint(connection, payer, payer.publicKey, null, 6);
+    rewardMint = await createMint(connection, payer, payer.publicKey, null, 6);
+    payerStakeAccount = await tokenAccount(stakeMint, payer.publicKey);
+    payerRewardAccount = await tokenAccount(rewardMint, payer.publicKey);
+    stakerStakeAccount = await tokenAccount(stakeMint, staker.publicKey);
+    stakerRewardAccount = await tokenAccount(rewardMint, staker.publicKey);
+    await mintTo(
+      connection,
+      payer,
+      stakeMint,
+      stakerStakeAccount,
+      payer,
+      stakeAmount.add(topUp).toNumber()
+    );
+    await mintTo(
+      connection,
+      payer,
+      rewardMint,
+      payerRewardAccount,
+      payer,
+      1e12
+    );
+
+    await program.methods
+      .initPool({
+        authority: payer.publicKey,
+        tokenAddress: stakeMint,
+        apr: new BN(100_000_000),
+        isActive: true,
+        endDate: null,
+        identifier,
+        poolName: "Emergency test pool",
+        defaultMultiplier: new BN(10_000),
+        amount: new BN(1e12),
+        lockPolicy: { reset: {} },
+        feeConfig: null,
+        lockTiers: [{ minStakeSeconds: 0, aprBps: 10_000 }],
+        boostCurve: { none: {} },
+        rewardMode: { apr: {} },
+      })
+      .accounts({
+        stakePool,
+        rewardVault: getAssociatedTokenAddressSync(rewardMint, stakePool, true),
+        mint: stakeMint,
+        rewardMint,
+        globalConfig,
+        feeRecipient: config.feeRecipient,
+        payerRewardTokenAccount: payerRewardAccount,
+        payer: payer.publicKey,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+
+    stakeEntry = web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("stake-entry"),
+        stakePool.toBuffer(),
+        stakeMint.toBuffer(),
+        staker.publicKey.toBuffer(),
+      ],
+      program.programId
+    )[0];
+    entryTokenAccount = getAssociatedTokenAddressSync(
+      stakeMint,
+      stakeEntry,
+      true
+    );
+    await program.methods
+      .initStakeEntry()
+      .accounts({
+        stakeEntry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        payer: staker.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([staker])
+      .rpc();
+    await stake(stakeAmount);
+  });
+
+  it("refuses while disabled", async () => {
+    await expectError(emergencyWithdraw(), "EmergencyWithdrawDisabled");
+  });
+
+  it("returns the principal minus the fee and forfeits rewards", async () => {
+    await program.methods
+      .setEmergencyWithdraw({ enabled: true, feeBps })
+      .accounts({ stakePool, globalConfig, payer: payer.publicKey })
+      .rpc();
+
+    // The top-up settles the rewards accrued so far into the entry.
+    await sleep(2_000);
+    await stake(topUp);
+    const before = await program.account.stakeEntry.fetch(stakeEntry);
+    assert.ok(before.pendingRewards.gtn(0));
+
+    const poolBefore = await program.account.stakePool.fetch(stakePool);
+    const stakerBefore = await balance(stakerStakeAccount);
+    const ownerBefore = await balance(payerStakeAccount);
+    const rewardBefore = await balance(stakerRewardAccount);
+
+    await emergencyWithdraw();
+
+    const amount = before.amount;
+    const fee = amount.muln(feeBps).addn(9_999).divn(10_000);
+    assert.strictEqual(
+      (await balance(stakerStakeAccount)).sub(stakerBefore).toString(),
+      amount.sub(fee).toString()
+    );
+    assert.strictEqual(
+      (await balance(payerStakeAccount)).sub(ownerBefore).toString(),
+      fee.toString()
+    );
+    assert.strictEqual(
+      (await balance(stakerRewardAccount)).toString(),
+      rewardBefore.toString()
+    );
+
+    const entry = await program.account.stakeEntry.fetch(stakeEntry);
+    assert.ok(entry.amount.isZero());
+    assert.ok(entry.pendingRewards.isZero());
+    assert.ok(entry.rewardIou.isZero());
+
+    const pool = await program.account.stakePool.fetch(stakePool);
+    assert.strictEqual(
+      poolBefore.totalStaked.sub(pool.totalStaked).toString(),
+      amount.toString()
+    );
+    assert.strictEqual(
+      poolBefore.totalRewardsOwed.sub(pool.totalRewardsOwed).toString(),
+      before.pendingRewards.toString()
+    );
+  });
+});
@@ -0,0 +1,354 @@
+import * as anchor from "@coral-xyz/anchor";
+import BN from "bn.js";
+import assert from "assert";
+import * as web3 from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import type { Utils } from "../target/types/utils";
+
+const BPF_LOADER_UPGRADEABLE_PROGRAM_ID = new web3.PublicKey(
+  "BPFLoaderUpgradeab1e11111111111111111111111"
+);
+
+const REWARD_PRECISION = new BN("1000000000000");
+const BASIS_POINTS = new BN(10_000);
+
+const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+
+describe("reward streams", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Utils as anchor.Program<Utils>;
+  const wallet = provider.wallet as anchor.Wallet;
+  const payer = wallet.payer;
+  const staker = web3.Keypair.generate();
+  const partner = web3.Keypair.generate();
+
+  const identifier = `stream-test-${Date.now()}`;
+  const payerStake = new BN(3_000_000_000);
+  const stakerStake = new BN(1_000_000_000);
+  const ratePerSecond = new BN(1_000_000);
+
+  const [globalConfig] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("global-config")],
+    program.programId
+  );
+  const [stakePool] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("stake-pool"), Buffer.from(identifier)],
+    program.programId
+  );
+  const [rewardStream] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("reward-stream"), stakePool.toBuffer(), Buffer.from([0])],
+    program.programId
+  );
+
+  let stakeMint: web3.PublicKey;
+  let rewardMint: web3.PublicKey;
+  let streamMint: web3.PublicKey;
+  let rewardVault: web3.PublicKey;
+  let streamVault: web3.PublicKey;
+
+  // Token accounts and stake entry of one staker.
+  type Position = {
+    owner: web3.Keypair;
+    stakeAccount: web3.PublicKey;
+    rewardAccount: web3.PublicKey;
+    streamAccount: web3.PublicKey;
+    stakeEntry: web3.PublicKey;
+    entryTokenAccount: web3.PublicKey;
+  };
+  let payerPosition: Position;
+  let stakerPosition: Position;
+
+  const balance = async (account: web3.PublicKey) =>
+    new BN(
+      (await getAccount(provider.connection, account)).amount.toString()
+    );
+
+  const tokenAccount = async (mint: web3.PublicKey, owner: web3.PublicKey) =>
+    (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payer,
+        mint,
+        owner
+      )
+    ).address;
+
+  const openPosition = async (
+    owner: web3.Keypair,
+    amount: BN
+  ): Promise<Position> => {
+    const stakeAccount = await tokenAccount(stakeMint, owner.publicKey);
+    await mintTo(
+      provider.connection,
+      payer,
+      stakeMint,
+      stakeAccount,
+      payer,
+      amount.toNumber()
+    );
+    const [stakeEntry] = web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("stake-entry"),
+        stakePool.toBuffer(),
+        stakeMint.toBuffer(),
+        owner.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const entryTokenAccount = getAssociatedTokenAddressSync(
+      stakeMint,
+      stakeEntry,
+      true
+    );
+
+    await program.methods
+      .initStakeEntry()
+      .accounts({
+        stakeEntry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        payer: owner.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([owner])
+      .rpc();
+    await program.methods
+      .stakeToken({ amount, tier: 0 })
+      .accounts({
+        stakeEntry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        payerTokenAccount: stakeAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+        payer: owner.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+
+    return {
+      owner,
+      stakeAccount,
+      rewardAccount: await tokenAccount(rewardMint, owner.publicKey),
+      streamAccount: await tokenAccount(streamMint, owner.publicKey),
+      stakeEntry,
+      entryTokenAccount,
+    };
+  };
+
+  const claimInstruction = (position: Position) =>
+    program.methods
+      .claimToken()
+      .accounts({
+        stakeEntry: position.stakeEntry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount: position.entryTokenAccount,
+        rewardVault,
+        rewardMint,
+        ownerRewardTokenAccount: position.rewardAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+        payer: position.owner.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .remainingAccounts([
+        { pubkey: rewardStream, isSigner: false, isWritable: true },
+        { pubkey: streamVault, isSigner: false, isWritable: true },
+        { pubkey: streamMint, isSigner: false, isWritable: false },
+        { pubkey: position.streamAccount, isSigner: false, isWritable: true },
+      ])
+      .instruction();
+
+  before(async () => {
+    const connection = provider.connection;
+    if ((await connection.getAccountInfo(globalConfig)) === null) {
+      const [programData] = web3.PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        BPF_LOADER_UPGRADEABLE_PROGRAM_ID
+      );
+      await program.methods
+        .initGlobalConfig({
+          superAdmin: payer.publicKey,
+          feeRecipient: payer.publicKey,
+          platformFee: new BN(0),
+        })
+        .accounts({
+          globalConfig,
+          program: program.programId,
+          programData,
+          payer: payer.publicKey,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+    }
+    const config = await program.account.globalConfig.fetch(globalConfig);
+
+    await provider.sendAndConfirm(
+      new web3.Transaction().add(
+        web3.SystemProgram.transfer({
+          fromPubkey: payer.publicKey,
+          toPubkey: staker.publicKey,
+          lamports: web3.LAMPORTS_PER_SOL / 10,
+        }),
+        web3.SystemProgram.transfer({
+          fromPubkey: payer.publicKey,
+          toPubkey: partner.publicKey,
+          lamports: web3.LAMPORTS_PER_SOL / 10,
+        })
+      )
+    );
+
+    stakeMint = await createMint(connection, payer, payer.publicKey, null, 6);
+    rewardMint = await createMint(connection, payer, payer.publicKey, null, 6);
+    streamMint = await createMint(connection, payer, payer.publicKey, null, 6);
+
+    const payerRewardAccount = await tokenAccount(rewardMint, payer.publicKey);
+    await mintTo(
+      connection,
+      payer,
+      rewardMint,
+      payerRewardAccount,
+      payer,
+      1e12
+    );
+
+    rewardVault = getAssociatedTokenAddressSync(rewardMint, stakePool, true);
+    await program.methods
+      .initPool({
+        authority: payer.publicKey,
+        tokenAddress: stakeMint,
+        apr: new BN(1_000),
+        isActive: true,
+        endDate: null,
+        identifier,
+        poolName: "Stream test pool",
+        defaultMultiplier: new BN(10_000),
+        amount: new BN(1e12),
+        lockPolicy: { reset: {} },
+        feeConfig: null,
+        lockTiers: [{ minStakeSeconds: 0, aprBps: 10_000 }],
+        boostCurve: { none: {} },
+        rewardMode: { apr: {} },
+      })
+      .accounts({
+        stakePool,
+        rewardVault,
+        mint: stakeMint,
+        rewardMint,
+        globalConfig,
+        feeRecipient: config.feeRecipient,
+        payerRewardTokenAccount: payerRewardAccount,
+        payer: payer.publicKey,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+
+    payerPosition = await openPosition(payer, payerStake);
+    stakerPosition = await openPosition(staker, stakerStake);
+
+    // The partner funds the stream in its own token; the pool authority
+    // only approves it.
+    const partnerStreamAccount = await tokenAccount(
+      streamMint,
+      partner.publicKey
+    );
+    const funding = ratePerSecond.muln(3_600);
+    await mintTo(
+      connection,
+      payer,
+      streamMint,
+      partnerStreamAccount,
+      payer,
+      funding.toNumber()
+    );
+    streamVault = getAssociatedTokenAddressSync(streamMint, rewardStream, true);
+    await program.methods
+      .addRewardStream({
+        ratePerSecond,
+        endTime: new BN(Math.floor(Date.now() / 1000) + 3_600),
+        amount: funding,
+      })
+      .accounts({
+        stakePool,
+        poolAuthority: payer.publicKey,
+        rewardStream,
+        rewardMint: streamMint,
+        rewardVault: streamVault,
+        payerTokenAccount: partnerStreamAccount,
+        payer: partner.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      })
+      .signers([partner])
+      .rpc();
+  });
+
+  it("records the partner as the stream authority", async () => {
+    const stream = await program.account.rewardStream.fetch(rewardStream);
+    assert.ok(stream.authority.equals(partner.publicKey));
+  });
+
+  it("pays every staker its share of the stream on claim", async () => {
+    const payerBefore = await balance(payerPosition.streamAccount);
+    const stakerBefore = await balance(stakerPosition.streamAccount);
+    await sleep(3_000);
+
+    // Both claims land in the same transaction, so they see the same
+    // accumulator.
+    await provider.sendAndConfirm(
+      new web3.Transaction().add(
+        await claimInstruction(payerPosition),
+        await claimInstruction(stakerPosition)
+      ),
+      [staker]
+    );
+
+    const stream = await program.account.rewardStream.fetch(rewardStream);
+    const rewardPerWeight = stream.rewardPerWeightStored;
+    assert.ok(rewardPerWeight.gtn(0));
+
+    // Mirrors `StakeEntry::earned` with a 1x tier.
+    const expected = (amount: BN) =>
+      amount.mul(rewardPerWeight).div(REWARD_PRECISION.mul(BASIS_POINTS));
+    const payerPaid = (await balance(payerPosition.streamAccount)).sub(
+      payerBefore
+    );
+    const stakerPaid = (await balance(stakerPosition.streamAccount)).sub(
+      stakerBefore
+    );
+    assert.strictEqual(payerPaid.toString(), expected(payerStake).toString());
+    assert.strictEqual(
+      stakerPaid.toString(),
+      expected(stakerStake).toString()
+    );
+
+    // 3:1 stake, so 3:1 rewards up to rounding.
+    assert.ok(payerPaid.sub(stakerPaid.muln(3)).abs().lten(3));
+    assert.strictEqual(
+      stream.totalPaid.toString(),
+      payerPaid.add(stakerPaid).toString()
+    );
+  });
+});
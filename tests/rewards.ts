@@ -0,0 +1,302 @@
+import * as anchor from "@coral-xyz/anchor";
+import BN from "bn.js";
+import assert from "assert";
+import * as web3 from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import type { Utils } from "../target/types/utils";
+
+const BPF_LOADER_UPGRADEABLE_PROGRAM_ID = new web3.PublicKey(
+  "BPFLoaderUpgradeab1e11111111111111111111111"
+);
+
+const SECONDS_PER_YEAR = new BN(365 * 24 * 60 * 60);
+const REWARD_PRECISION = new BN("1000000000000");
+const BASIS_POINTS = new BN(10_000);
+
+const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+
+describe("reward accrual", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Utils as anchor.Program<Utils>;
+  const wallet = provider.wallet as anchor.Wallet;
+  const payer = wallet.payer;
+
+  const identifier = `reward-test-${Date.now()}`;
+  const apr = new BN(100_000_000);
+  const defaultMultiplier = new BN(10_000);
+  const tierApr = new BN(10_000);
+  const stakeAmount = new BN(1_000_000_000);
+
+  const [globalConfig] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("global-config")],
+    program.programId
+  );
+  const [stakePool] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("stake-pool"), Buffer.from(identifier)],
+    program.programId
+  );
+
+  let stakeMint: web3.PublicKey;
+  let rewardMint: web3.PublicKey;
+  let payerStakeAccount: web3.PublicKey;
+  let payerRewardAccount: web3.PublicKey;
+  let superAdminStakeAccount: web3.PublicKey;
+  let rewardVault: web3.PublicKey;
+  let stakeEntry: web3.PublicKey;
+  let entryTokenAccount: web3.PublicKey;
+  let superAdmin: web3.PublicKey;
+
+  const balance = async (account: web3.PublicKey) =>
+    new BN(
+      (await getAccount(provider.connection, account)).amount.toString()
+    );
+
+  // Mirrors `reward_per_token_between` and `StakeEntry::earned`, both of
+  // which round down.
+  const expectedRewards = (amount: BN, elapsed: number) => {
+    const rewardPerToken = new BN(elapsed)
+      .mul(apr)
+      .mul(defaultMultiplier)
+      .mul(REWARD_PRECISION)
+      .div(SECONDS_PER_YEAR.mul(BASIS_POINTS));
+    return amount
+      .mul(rewardPerToken.mul(tierApr).div(BASIS_POINTS))
+      .div(REWARD_PRECISION.mul(BASIS_POINTS));
+  };
+
+  before(async () => {
+    const connection = provider.connection;
+    if ((await connection.getAccountInfo(globalConfig)) === null) {
+      const [programData] = web3.PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        BPF_LOADER_UPGRADEABLE_PROGRAM_ID
+      );
+      await program.methods
+        .initGlobalConfig({
+          superAdmin: payer.publicKey,
+          feeRecipient: payer.publicKey,
+          platformFee: new BN(0),
+        })
+        .accounts({
+          globalConfig,
+          program: program.programId,
+          programData,
+          payer: payer.publicKey,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+    }
+    const config = await program.account.globalConfig.fetch(globalConfig);
+    superAdmin = config.superAdmin;
+
+    stakeMint = await createMint(connection, payer, payer.publicKey, null, 6);
+    rewardMint = await createMint(connection, payer, payer.publicKey, null, 6);
+
+    payerStakeAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        connection,
+        payer,
+        stakeMint,
+        payer.publicKey
+      )
+    ).address;
+    payerRewardAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        connection,
+        payer,
+        rewardMint,
+        payer.publicKey
+      )
+    ).address;
+    superAdminStakeAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        connection,
+        payer,
+        stakeMint,
+        superAdmin
+      )
+    ).address;
+    await mintTo(connection, payer, stakeMint, payerStakeAccount, payer, 1e10);
+    await mintTo(
+      connection,
+      payer,
+      rewardMint,
+      payerRewardAccount,
+      payer,
+      1e13
+    );
+
+    rewardVault = getAssociatedTokenAddressSync(rewardMint, stakePool, true);
+    await program.methods
+      .initPool({
+        authority: payer.publicKey,
+        tokenAddress: stakeMint,
+        apr,
+        isActive: true,
+        endDate: null,
+        identifier,
+        poolName: "Reward test pool",
+        defaultMultiplier,
+        amount: new BN(1e12),
+        lockPolicy: { reset: {} },
+        feeConfig: null,
+        lockTiers: [{ minStakeSeconds: 0, aprBps: tierApr.toNumber() }],
+        boostCurve: { none: {} },
+        rewardMode: { apr: {} },
+      })
+      .accounts({
+        stakePool,
+        rewardVault,
+        mint: stakeMint,
+        rewardMint,
+        globalConfig,
+        feeRecipient: config.feeRecipient,
+        payerRewardTokenAccount: payerRewardAccount,
+        payer: payer.publicKey,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+
+    stakeEntry = web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("stake-entry"),
+        stakePool.toBuffer(),
+        stakeMint.toBuffer(),
+        payer.publicKey.toBuffer(),
+      ],
+      program.programId
+    )[0];
+    entryTokenAccount = getAssociatedTokenAddressSync(
+      stakeMint,
+      stakeEntry,
+      true
+    );
+    await program.methods
+      .initStakeEntry()
+      .accounts({
+        stakeEntry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        payer: payer.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+
+    await program.methods
+      .stakeToken({ amount: stakeAmount, tier: 0 })
+      .accounts({
+        stakeEntry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        payerTokenAccount: payerStakeAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+        payer: payer.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+  });
+
+  it("pays amount x time x rate on claim", async () => {
+    const before = await program.account.stakePool.fetch(stakePool);
+    const rewardBefore = await balance(payerRewardAccount);
+    await sleep(3_000);
+
+    await program.methods
+      .claimToken()
+      .accounts({
+        stakeEntry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        rewardVault,
+        rewardMint,
+        ownerRewardTokenAccount: payerRewardAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+        payer: payer.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const after = await program.account.stakePool.fetch(stakePool);
+    const elapsed = after.lastRewardUpdate.sub(before.lastRewardUpdate);
+    assert.ok(elapsed.gtn(0), "clock did not advance");
+
+    const claimed = (await balance(payerRewardAccount)).sub(rewardBefore);
+    const expected = expectedRewards(stakeAmount, elapsed.toNumber());
+    assert.ok(expected.gtn(0));
+    assert.strictEqual(claimed.toString(), expected.toString());
+
+    const entry = await program.account.stakeEntry.fetch(stakeEntry);
+    assert.ok(entry.pendingRewards.isZero());
+    assert.ok(entry.rewardIou.isZero());
+  });
+
+  it("pays the withdrawn share of rewards on a partial unstake", async () => {
+    const before = await program.account.stakePool.fetch(stakePool);
+    const rewardBefore = await balance(payerRewardAccount);
+    const unstakeAmount = stakeAmount.divn(2);
+    await sleep(3_000);
+
+    await program.methods
+      .unstakeToken({ amount: unstakeAmount })
+      .accounts({
+        stakeEntry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        rewardVault,
+        rewardMint,
+        payerTokenAccount: payerStakeAccount,
+        payerRewardTokenAccount: payerRewardAccount,
+        globalConfig,
+        superAdmin,
+        superAdminTokenAccount: superAdminStakeAccount,
+        poolOwnerTokenAccount: payerStakeAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+        payer: payer.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const after = await program.account.stakePool.fetch(stakePool);
+    const elapsed = after.lastRewardUpdate.sub(before.lastRewardUpdate);
+    assert.ok(elapsed.gtn(0), "clock did not advance");
+
+    const pending = expectedRewards(stakeAmount, elapsed.toNumber());
+    const share = pending.mul(unstakeAmount).div(stakeAmount);
+    const paid = (await balance(payerRewardAccount)).sub(rewardBefore);
+    assert.strictEqual(paid.toString(), share.toString());
+
+    const entry = await program.account.stakeEntry.fetch(stakeEntry);
+    assert.strictEqual(
+      entry.amount.toString(),
+      stakeAmount.sub(unstakeAmount).toString()
+    );
+    assert.strictEqual(
+      entry.pendingRewards.toString(),
+      pending.sub(share).toString()
+    );
+  });
+});
@@ -0,0 +1,265 @@
+import * as anchor from "@coral-xyz/anchor";
+import BN from "bn.js";
+import assert from "assert";
+import * as web3 from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  createMint,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import type { Utils } from "../target/types/utils";
+
+const BPF_LOADER_UPGRADEABLE_PROGRAM_ID = new web3.PublicKey(
+  "BPFLoaderUpgradeab1e11111111111111111111111"
+);
+
+async function expectError(promise: Promise<unknown>, code: string) {
+  let error: unknown;
+  try {
+    await promise;
+  } catch (e) {
+    error = e;
+  }
+  assert.ok(
+    error instanceof anchor.AnchorError,
+    `expected ${code}, got ${error}`
+  );
+  assert.strictEqual(error.error.errorCode.code, code);
+}
+
+describe("stake mint consistency", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Utils as anchor.Program<Utils>;
+  const wallet = provider.wallet as anchor.Wallet;
+  const payer = wallet.payer;
+
+  const identifier = `mint-test-${Date.now()}`;
+  const [globalConfig] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("global-config")],
+    program.programId
+  );
+  const [stakePool] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("stake-pool"), Buffer.from(identifier)],
+    program.programId
+  );
+
+  let stakeMint: web3.PublicKey;
+  let otherMint: web3.PublicKey;
+  let payerStakeAccount: web3.PublicKey;
+  let payerOtherAccount: web3.PublicKey;
+  let rewardVault: web3.PublicKey;
+  let stakeEntry: web3.PublicKey;
+  let entryTokenAccount: web3.PublicKey;
+
+  const entryAddress = (mint: web3.PublicKey) =>
+    web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("stake-entry"),
+        stakePool.toBuffer(),
+        mint.toBuffer(),
+        payer.publicKey.toBuffer(),
+      ],
+      program.programId
+    )[0];
+
+  before(async () => {
+    if ((await provider.connection.getAccountInfo(globalConfig)) === null) {
+      const [programData] = web3.PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        BPF_LOADER_UPGRADEABLE_PROGRAM_ID
+      );
+      await program.methods
+        .initGlobalConfig({
+          superAdmin: payer.publicKey,
+          feeRecipient: payer.publicKey,
+          platformFee: new BN(0),
+        })
+        .accounts({
+          globalConfig,
+          program: program.programId,
+          programData,
+          payer: payer.publicKey,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+    }
+
+    const connection = provider.connection;
+    stakeMint = await createMint(connection, payer, payer.publicKey, null, 6);
+    otherMint = await createMint(connection, payer, payer.publicKey, null, 6);
+
+    payerStakeAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        connection,
+        payer,
+        stakeMint,
+        payer.publicKey
+      )
+    ).address;
+    payerOtherAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        connection,
+        payer,
+        otherMint,
+        payer.publicKey
+      )
+    ).address;
+    await mintTo(connection, payer, stakeMint, payerStakeAccount, payer, 1e9);
+    await mintTo(connection, payer, otherMint, payerOtherAccount, payer, 1e9);
+
+    rewardVault = getAssociatedTokenAddressSync(stakeMint, stakePool, true);
+    await program.methods
+      .initPool({
+        authority: payer.publicKey,
+        tokenAddress: stakeMint,
+        apr: new BN(1_000),
+        isActive: true,
+        endDate: null,
+        identifier,
+        poolName: "Mint test pool",
+        defaultMultiplier: new BN(10_000),
+        amount: new BN(100_000_000),
+        lockPolicy: { reset: {} },
+        feeConfig: null,
+        lockTiers: [{ minStakeSeconds: 0, aprBps: 10_000 }],
+        boostCurve: { none: {} },
+        rewardMode: { apr: {} },
+      })
+      .accounts({
+        stakePool,
+        rewardVault,
+        mint: stakeMint,
+        rewardMint: stakeMint,
+        globalConfig,
+        feeRecipient: payer.publicKey,
+        payerRewardTokenAccount: payerStakeAccount,
+        payer: payer.publicKey,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+
+    stakeEntry = entryAddress(stakeMint);
+    entryTokenAccount = getAssociatedTokenAddressSync(
+      stakeMint,
+      stakeEntry,
+      true
+    );
+    await program.methods
+      .initStakeEntry()
+      .accounts({
+        stakeEntry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        payer: payer.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+  });
+
+  it("rejects a stake entry for a mint other than the pool token", async () => {
+    const otherEntry = entryAddress(otherMint);
+    await expectError(
+      program.methods
+        .initStakeEntry()
+        .accounts({
+          stakeEntry: otherEntry,
+          stakePool,
+          stakeMint: otherMint,
+          entryTokenAccount: getAssociatedTokenAddressSync(
+            otherMint,
+            otherEntry,
+            true
+          ),
+          payer: payer.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: web3.SystemProgram.programId,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          rent: web3.SYSVAR_RENT_PUBKEY,
+        })
+        .rpc(),
+      "InvalidStakeMint"
+    );
+  });
+
+  it("rejects staking with a mismatched mint", async () => {
+    await expectError(
+      program.methods
+        .stakeToken({ amount: new BN(1_000_000), tier: 0 })
+        .accounts({
+          stakeEntry,
+          stakePool,
+          stakeMint: otherMint,
+          entryTokenAccount,
+          payerTokenAccount: payerOtherAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          rent: web3.SYSVAR_RENT_PUBKEY,
+          payer: payer.publicKey,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc(),
+      "InvalidStakeMint"
+    );
+  });
+
+  it("rejects claiming with a mismatched mint", async () => {
+    await expectError(
+      program.methods
+        .claimToken()
+        .accounts({
+          stakeEntry,
+          stakePool,
+          stakeMint: otherMint,
+          entryTokenAccount,
+          rewardVault,
+          rewardMint: stakeMint,
+          ownerRewardTokenAccount: payerStakeAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rewardTokenProgram: TOKEN_PROGRAM_ID,
+          payer: payer.publicKey,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc(),
+      "InvalidStakeMint"
+    );
+  });
+
+  it("rejects unstaking with a mismatched mint", async () => {
+    await expectError(
+      program.methods
+        .unstakeToken({ amount: new BN(1_000_000) })
+        .accounts({
+          stakeEntry,
+          stakePool,
+          stakeMint: otherMint,
+          entryTokenAccount,
+          rewardVault,
+          rewardMint: stakeMint,
+          payerTokenAccount: payerOtherAccount,
+          payerRewardTokenAccount: payerStakeAccount,
+          globalConfig,
+          superAdmin: payer.publicKey,
+          superAdminTokenAccount: payerOtherAccount,
+          poolOwnerTokenAccount: payerOtherAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rewardTokenProgram: TOKEN_PROGRAM_ID,
+          payer: payer.publicKey,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc(),
+      "InvalidStakeMint"
+    );
+  });
+});
@@ -0,0 +1,321 @@
+import * as anchor from "@coral-xyz/anchor";
+import BN from "bn.js";
+import assert from "assert";
+import * as web3 from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import type { Utils } from "../target/types/utils";
+
+const BPF_LOADER_UPGRADEABLE_PROGRAM_ID = new web3.PublicKey(
+  "BPFLoaderUpgradeab1e11111111111111111111111"
+);
+
+const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+
+async function expectError(promise: Promise<unknown>, code: string) {
+  let error: unknown;
+  try {
+    await promise;
+  } catch (e) {
+    error = e;
+  }
+  assert.ok(
+    error instanceof anchor.AnchorError,
+    `expected ${code}, got ${error}`
+  );
+  assert.strictEqual(error.error.errorCode.code, code);
+}
+
+describe("underfunded reward vault", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Utils as anchor.Program<Utils>;
+  const wallet = provider.wallet as anchor.Wallet;
+  const payer = wallet.payer;
+  const staker = web3.Keypair.generate();
+
+  const identifier = `underfunded-${Date.now()}`;
+  const stakeAmount = new BN(1_000_000_000);
+
+  const [globalConfig] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("global-config")],
+    program.programId
+  );
+  const [stakePool] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("stake-pool"), Buffer.from(identifier)],
+    program.programId
+  );
+
+  let stakeMint: web3.PublicKey;
+  let rewardMint: web3.PublicKey;
+  let payerStakeAccount: web3.PublicKey;
+  let payerRewardAccount: web3.PublicKey;
+  let stakerStakeAccount: web3.PublicKey;
+  let stakerRewardAccount: web3.PublicKey;
+  let superAdmin: web3.PublicKey;
+  let superAdminStakeAccount: web3.PublicKey;
+  let rewardVault: web3.PublicKey;
+  let entry: web3.PublicKey;
+  let entryTokenAccount: web3.PublicKey;
+
+  const balance = async (account: web3.PublicKey) =>
+    new BN(
+      (await getAccount(provider.connection, account)).amount.toString()
+    );
+
+  const tokenAccount = async (mint: web3.PublicKey, owner: web3.PublicKey) =>
+    (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payer,
+        mint,
+        owner
+      )
+    ).address;
+
+  const claim = () =>
+    program.methods
+      .claimToken()
+      .accounts({
+        stakeEntry: entry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        rewardVault,
+        rewardMint,
+        ownerRewardTokenAccount: stakerRewardAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+        payer: staker.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([staker])
+      .rpc();
+
+  before(async () => {
+    const connection = provider.connection;
+    if ((await connection.getAccountInfo(globalConfig)) === null) {
+      const [programData] = web3.PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        BPF_LOADER_UPGRADEABLE_PROGRAM_ID
+      );
+      await program.methods
+        .initGlobalConfig({
+          superAdmin: payer.publicKey,
+          feeRecipient: payer.publicKey,
+          platformFee: new BN(0),
+        })
+        .accounts({
+          globalConfig,
+          program: program.programId,
+          programData,
+          payer: payer.publicKey,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+    }
+    const config = await program.account.globalConfig.fetch(globalConfig);
+    superAdmin = config.superAdmin;
+
+    await provider.sendAndConfirm(
+      new web3.Transaction().add(
+        web3.SystemProgram.transfer({
+          fromPubkey: payer.publicKey,
+          toPubkey: staker.publicKey,
+          lamports: web3.LAMPORTS_PER_SOL / 10,
+        })
+      )
+    );
+
+    stakeMint = await createMint(connection, payer, payer.publicKey, null, 6);
+    rewardMint = await createMint(connection, payer, payer.publicKey, null, 6);
+    payerStakeAccount = await tokenAccount(stakeMint, payer.publicKey);
+    payerRewardAccount = await tokenAccount(rewardMint, payer.publicKey);
+    stakerStakeAccount = await tokenAccount(stakeMint, staker.publicKey);
+    stakerRewardAccount = await tokenAccount(rewardMint, staker.publicKey);
+    superAdminStakeAccount = await tokenAccount(stakeMint, superAdmin);
+    await mintTo(
+      connection,
+      payer,
+      stakeMint,
+      stakerStakeAccount,
+      payer,
+      stakeAmount.toNumber()
+    );
+    await mintTo(
+      connection,
+      payer,
+      rewardMint,
+      payerRewardAccount,
+      payer,
+      1e12
+    );
+
+    // A single unit of rewards, so the first payout already drains it.
+    rewardVault = getAssociatedTokenAddressSync(rewardMint, stakePool, true);
+    await program.methods
+      .initPool({
+        authority: payer.publicKey,
+        tokenAddress: stakeMint,
+        apr: new BN(100_000_000),
+        isActive: true,
+        endDate: null,
+        identifier,
+        poolName: "Underfunded test pool",
+        defaultMultiplier: new BN(10_000),
+        amount: new BN(1),
+        lockPolicy: { reset: {} },
+        feeConfig: null,
+        lockTiers: [{ minStakeSeconds: 0, aprBps: 10_000 }],
+        boostCurve: { none: {} },
+        rewardMode: { apr: {} },
+      })
+      .accounts({
+        stakePool,
+        rewardVault,
+        mint: stakeMint,
+        rewardMint,
+        globalConfig,
+        feeRecipient: config.feeRecipient,
+        payerRewardTokenAccount: payerRewardAccount,
+        payer: payer.publicKey,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+
+    entry = web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("stake-entry"),
+        stakePool.toBuffer(),
+        stakeMint.toBuffer(),
+        staker.publicKey.toBuffer(),
+      ],
+      program.programId
+    )[0];
+    entryTokenAccount = getAssociatedTokenAddressSync(stakeMint, entry, true);
+    await program.methods
+      .initStakeEntry()
+      .accounts({
+        stakeEntry: entry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        payer: staker.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([staker])
+      .rpc();
+    await program.methods
+      .stakeToken({ amount: stakeAmount, tier: 0 })
+      .accounts({
+        stakeEntry: entry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        payerTokenAccount: stakerStakeAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+        payer: staker.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([staker])
+      .rpc();
+  });
+
+  it("returns the principal and records an IOU on unstake", async () => {
+    const unstakeAmount = stakeAmount.divn(2);
+    const stakeBefore = await balance(stakerStakeAccount);
+    const rewardBefore = await balance(stakerRewardAccount);
+    await sleep(2_000);
+
+    await program.methods
+      .unstakeToken({ amount: unstakeAmount })
+      .accounts({
+        stakeEntry: entry,
+        stakePool,
+        stakeMint,
+        entryTokenAccount,
+        rewardVault,
+        rewardMint,
+        payerTokenAccount: stakerStakeAccount,
+        payerRewardTokenAccount: stakerRewardAccount,
+        globalConfig,
+        superAdmin,
+        superAdminTokenAccount: superAdminStakeAccount,
+        poolOwnerTokenAccount: payerStakeAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+        payer: staker.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([staker])
+      .rpc();
+
+    const pool = await program.account.stakePool.fetch(stakePool);
+    const unstakeFee = unstakeAmount
+      .muln(pool.feeConfig.unstakeFeeBps)
+      .addn(9_999)
+      .divn(10_000);
+    assert.strictEqual(
+      (await balance(stakerStakeAccount)).sub(stakeBefore).toString(),
+      unstakeAmount.sub(unstakeFee).toString()
+    );
+
+    // The vault held a single unit; the rest of the share is owed.
+    assert.strictEqual(
+      (await balance(stakerRewardAccount)).sub(rewardBefore).toString(),
+      "1"
+    );
+    assert.ok((await balance(rewardVault)).isZero());
+    const stakeEntryAccount = await program.account.stakeEntry.fetch(entry);
+    assert.ok(stakeEntryAccount.rewardIou.gtn(0));
+    assert.strictEqual(
+      pool.totalRewardIous.toString(),
+      stakeEntryAccount.rewardIou.toString()
+    );
+  });
+
+  it("fails a claim the empty vault cannot cover", async () => {
+    await expectError(claim(), "InsufficientRewards");
+  });
+
+  it("pays the IOU once the pool is refilled", async () => {
+    const iou = (await program.account.stakeEntry.fetch(entry)).rewardIou;
+    await program.methods
+      .fundPool(new BN(1e12))
+      .accounts({
+        stakePool,
+        rewardVault,
+        rewardMint,
+        payerRewardTokenAccount: payerRewardAccount,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+        payer: payer.publicKey,
+      })
+      .rpc();
+
+    const rewardBefore = await balance(stakerRewardAccount);
+    await claim();
+
+    const claimed = (await balance(stakerRewardAccount)).sub(rewardBefore);
+    assert.ok(claimed.gte(iou));
+    const stakeEntryAccount = await program.account.stakeEntry.fetch(entry);
+    assert.ok(stakeEntryAccount.rewardIou.isZero());
+    const pool = await program.account.stakePool.fetch(stakePool);
+    assert.ok(pool.totalRewardIous.isZero());
+  });
+});